@@ -0,0 +1,88 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// 同じディレクトリの一時ファイルに書いてから置き換える
+///
+/// 途中で中断されても、元のファイルか書き終えたファイルのどちらかが残る。
+/// 既存のファイルならパーミッション (Unix では所有者も) を引き継ぎ、
+/// シンボリックリンクならリンク先を置き換える。
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(".denux-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    temp.write_all(contents)?;
+
+    if let Ok(metadata) = fs::metadata(&path) {
+        temp.as_file().set_permissions(metadata.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+            // 所有者を変えられるのは特権ユーザーだけなので、失敗しても続ける
+            let _ = fchown(temp.as_file(), Some(metadata.uid()), Some(metadata.gid()));
+        }
+    }
+    temp.as_file().sync_all()?;
+    temp.persist(&path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_file_replaces_contents() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("index.ts");
+        fs::write(&path, "const a = 1;\n").unwrap();
+
+        write_file(&path, b"const a = 2;\n").expect("Failed to write file");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "const a = 2;\n");
+
+        let new_file = dir.path().join("new.ts");
+        write_file(&new_file, b"export {};\n").expect("Failed to write file");
+        assert_eq!(fs::read_to_string(&new_file).unwrap(), "export {};\n");
+
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(".denux-"))
+            .collect();
+        assert!(leftovers.is_empty(), "Temporary files should not be left");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("script.mjs");
+        fs::write(&path, "#!/usr/bin/env node\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let link = dir.path().join("link.mjs");
+        symlink(&path, &link).unwrap();
+
+        write_file(&link, b"#!/usr/bin/env node\nuseState();\n").expect("Failed to write file");
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#!/usr/bin/env node\nuseState();\n"
+        );
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+}
@@ -1,21 +1,123 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use denux::file_processor::validate_extension;
+use denux::filter::validate_glob;
+use denux::registry::{ImportStyle, NuxtVersion};
+use denux::report::OutputFormat;
 use std::path::PathBuf;
 
+/// 変更の出力先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// ファイルを直接書き換える
+    Files,
+    /// ファイルは書き換えず、`git apply` できるパッチファイルに書き出す
+    Patch,
+}
+
+/// 変換以外の操作
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// 直前の実行で書き換えたファイルを `.denux/` のバックアップから元に戻す
+    Undo,
+    /// ソースツリーを監視し、変更されたファイルをその都度処理する
+    Watch,
+    /// 標準入出力で Language Server Protocol のサーバーを動かす
+    Lsp,
+}
+
+/// `--stdin-filepath` が処理できる拡張子のファイルか確かめる
+fn validate_stdin_filepath(path: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(path);
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    validate_extension(extension).map(|_| path)
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "nuxt-auto-import-replacer")]
 #[command(about = "Replace auto-imports in Nuxt with explicit imports", long_about = None)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// 対象ディレクトリ (デフォルト: ./src)
-    #[arg(short, long, default_value = "src")]
+    #[arg(short, long, default_value = "src", global = true)]
     pub target: PathBuf,
 
     /// Dry-run: 変更を適用せずに表示のみ行う
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub dry_run: bool,
 
+    /// CI 向け: 変更を適用せずに確認し、変更が必要なら終了コード 1 (失敗時は 2) で終了する
+    #[arg(long)]
+    pub check: bool,
+
+    /// ステージされているファイルだけを、ステージされている内容で処理して結果をステージし直す
+    #[arg(long, conflicts_with = "since")]
+    pub staged: bool,
+
+    /// 指定した git の ref から変更されたファイルと、追跡されていないファイルだけを処理する
+    #[arg(long, value_name = "REF")]
+    pub since: Option<String>,
+
+    /// 標準入力のソースを変換して標準出力に書く (ファイルは書き換えない、エディタとの連携用)
+    #[arg(long, requires = "stdin_filepath")]
+    pub stdin: bool,
+
+    /// `--stdin` のソースのファイルパス (構文、SFC かどうか、使うレジストリを決める)
+    #[arg(long, value_name = "PATH", requires = "stdin", value_parser = validate_stdin_filepath)]
+    pub stdin_filepath: Option<PathBuf>,
+
+    /// 書き換えるファイルにコミットされていない変更があっても実行する
+    #[arg(long)]
+    pub allow_dirty: bool,
+
+    /// 変更の出力先 (files: ファイルを書き換える / patch: パッチファイルに書き出す)
+    #[arg(long, value_enum, default_value_t = Emit::Files)]
+    pub emit: Emit,
+
+    /// `--emit patch` で書き出すパッチファイル
+    #[arg(long, default_value = "denux.patch")]
+    pub patch_file: PathBuf,
+
+    /// 対象にするファイルの glob (対象ディレクトリからの相対パス、複数指定可)
+    #[arg(long, value_name = "GLOB", value_parser = validate_glob, global = true)]
+    pub include: Vec<String>,
+
+    /// 対象から外すファイルやディレクトリの glob (node_modules, .nuxt などのデフォルトに追加、複数指定可)
+    #[arg(long, value_name = "GLOB", value_parser = validate_glob, global = true)]
+    pub exclude: Vec<String>,
+
+    /// 処理する拡張子 (カンマ区切り、デフォルト: vue,js,mjs,jsx,ts,mts,cts,tsx)
+    #[arg(long, value_name = "EXT", value_delimiter = ',', value_parser = validate_extension, global = true)]
+    pub extensions: Option<Vec<String>>,
+
+    /// 設定ファイル (デフォルト: 対象ディレクトリから遡って見つけた denux.toml)
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// 結果の出力形式 (text / json / ndjson / sarif / github)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
     /// 詳細ログを出力する
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// 自動インポートの対象とする Nuxt のバージョン (デフォルト: package.json から検出)
+    #[arg(long, value_name = "VERSION", global = true)]
+    pub nuxt_version: Option<NuxtVersion>,
+
+    /// 追加する import のインポート元 (virtual: `#imports` / source: `vue` などの実モジュール)
+    #[arg(long, value_enum, default_value_t = ImportStyle::Virtual, global = true)]
+    pub import_style: ImportStyle,
+
+    /// 逆変換: Nuxt が自動インポートする名前の明示的な import を取り除く
+    #[arg(long, global = true)]
+    pub reverse: bool,
+
+    /// 使われていない自動インポート由来の import を取り除く
+    #[arg(long, global = true)]
+    pub remove_unused: bool,
 }
 
 #[cfg(test)]
@@ -27,9 +129,27 @@ mod tests {
     fn test_cli_default_values() {
         let args = Cli::parse_from(["nuxt-auto-import-replacer"]);
 
+        assert_eq!(args.command, None);
         assert_eq!(args.target, PathBuf::from("src"));
         assert!(!args.dry_run);
+        assert!(!args.check);
+        assert!(!args.staged);
+        assert_eq!(args.since, None);
+        assert!(!args.stdin);
+        assert_eq!(args.stdin_filepath, None);
+        assert!(!args.allow_dirty);
+        assert_eq!(args.emit, Emit::Files);
+        assert_eq!(args.patch_file, PathBuf::from("denux.patch"));
+        assert!(args.include.is_empty());
+        assert!(args.exclude.is_empty());
+        assert_eq!(args.extensions, None);
+        assert_eq!(args.config, None);
+        assert_eq!(args.format, OutputFormat::Text);
         assert!(!args.verbose);
+        assert_eq!(args.nuxt_version, None);
+        assert_eq!(args.import_style, ImportStyle::Virtual);
+        assert!(!args.reverse);
+        assert!(!args.remove_unused);
     }
 
     #[test]
@@ -39,12 +159,93 @@ mod tests {
             "--target",
             "my_project",
             "--dry-run",
+            "--check",
+            "--since",
+            "origin/main",
+            "--allow-dirty",
+            "--emit",
+            "patch",
+            "--patch-file",
+            "changes.patch",
+            "--include",
+            "pages/**",
+            "--exclude",
+            "legacy/**",
+            "--exclude",
+            "**/*.spec.ts",
+            "--extensions",
+            "vue,.tsx",
+            "--config",
+            "ci.toml",
+            "--format",
+            "ndjson",
             "--verbose",
+            "--nuxt-version",
+            "3.10",
+            "--import-style",
+            "source",
+            "--reverse",
+            "--remove-unused",
         ]);
 
         assert_eq!(args.target, PathBuf::from("my_project"));
         assert!(args.dry_run);
+        assert!(args.check);
+        assert_eq!(args.since, Some("origin/main".to_string()));
+        assert!(args.allow_dirty);
+        assert_eq!(args.emit, Emit::Patch);
+        assert_eq!(args.patch_file, PathBuf::from("changes.patch"));
+        assert_eq!(args.include, vec!["pages/**"]);
+        assert_eq!(args.exclude, vec!["legacy/**", "**/*.spec.ts"]);
+        assert_eq!(args.extensions, Some(vec!["vue".into(), "tsx".into()]));
+        assert_eq!(args.config, Some(PathBuf::from("ci.toml")));
+        assert_eq!(args.format, OutputFormat::Ndjson);
         assert!(args.verbose);
+        assert_eq!(args.nuxt_version, Some(NuxtVersion::new(3, 10)));
+        assert_eq!(args.import_style, ImportStyle::Source);
+        assert!(args.reverse);
+        assert!(args.remove_unused);
+    }
+
+    #[test]
+    fn test_cli_rejects_invalid_values() {
+        let result = Cli::try_parse_from(["nuxt-auto-import-replacer", "--exclude", "pages/["]);
+        assert!(result.is_err());
+
+        let result = Cli::try_parse_from(["nuxt-auto-import-replacer", "--extensions", "svelte"]);
+        assert!(result.is_err());
+
+        let result =
+            Cli::try_parse_from(["nuxt-auto-import-replacer", "--staged", "--since", "HEAD"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_subcommands() {
+        let args = Cli::parse_from(["nuxt-auto-import-replacer", "undo", "-t", "app"]);
+
+        assert_eq!(args.command, Some(Command::Undo));
+        assert_eq!(args.target, PathBuf::from("app"));
+
+        let args = Cli::parse_from(["nuxt-auto-import-replacer", "watch", "--dry-run"]);
+        assert_eq!(args.command, Some(Command::Watch));
+        assert!(args.dry_run);
+
+        let args = Cli::try_parse_from(["nuxt-auto-import-replacer", "lsp"]).unwrap();
+        assert_eq!(args.command, Some(Command::Lsp));
+    }
+
+    #[test]
+    fn test_cli_stdin_mode() {
+        let args = Cli::parse_from([
+            "nuxt-auto-import-replacer",
+            "--stdin",
+            "--stdin-filepath",
+            "pages/index.vue",
+        ]);
+
+        assert!(args.stdin);
+        assert_eq!(args.stdin_filepath, Some(PathBuf::from("pages/index.vue")));
     }
 
     #[test]
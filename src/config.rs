@@ -0,0 +1,122 @@
+use crate::error::Error;
+use crate::file_processor;
+use crate::filter;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 設定ファイルの名前
+pub const CONFIG_FILE: &str = "denux.toml";
+
+/// `denux.toml` の内容
+///
+/// ```toml
+/// include = ["pages/**", "components/**"]
+/// exclude = ["legacy/**"]
+/// extensions = ["vue", "ts"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 対象にするファイルの glob (空ならすべて)
+    pub include: Vec<String>,
+    /// 対象から外すファイルやディレクトリの glob (デフォルトの除外に追加される)
+    pub exclude: Vec<String>,
+    /// 処理する拡張子 (指定がなければデフォルト)
+    pub extensions: Option<Vec<String>>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path).map_err(|e| Error::Config {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        let mut config: Self = toml::from_str(&content).map_err(|e| Error::Config {
+            path: path.to_path_buf(),
+            message: e.message().to_string(),
+        })?;
+
+        let invalid = |message| Error::Config {
+            path: path.to_path_buf(),
+            message,
+        };
+        for pattern in config.include.iter().chain(&config.exclude) {
+            filter::validate_glob(pattern).map_err(invalid)?;
+        }
+        // CLI の `--extensions` と同じく `.ts` のような先頭のドットを取り除いた値にする
+        if let Some(extensions) = config.extensions.take() {
+            let extensions = extensions
+                .iter()
+                .map(|extension| file_processor::validate_extension(extension))
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+            config.extensions = Some(extensions);
+        }
+        Ok(config)
+    }
+
+    /// `dir` から親ディレクトリへ遡って `denux.toml` を探す
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        let dir = dir.canonicalize().ok()?;
+        dir.ancestors()
+            .map(|ancestor| ancestor.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// `path` が指定されていればそれを、なければ `dir` から見つけた設定ファイルを読む
+    ///
+    /// 設定ファイルがなければデフォルトの設定になる。
+    pub fn load(path: Option<&Path>, dir: &Path) -> Result<Self, Error> {
+        match path.map(Path::to_path_buf).or_else(|| Self::find(dir)) {
+            Some(path) => Self::from_file(&path),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_config_from_ancestor() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        fs::write(
+            dir.path().join(CONFIG_FILE),
+            "include = [\"pages/**\"]\nexclude = [\"legacy/**\"]\nextensions = [\"vue\", \".ts\"]\n",
+        )
+        .expect("Failed to write config");
+        let src = dir.path().join("src");
+        fs::create_dir(&src).expect("Failed to create src dir");
+
+        let config = Config::load(None, &src).expect("Failed to load config");
+        assert_eq!(config.include, vec!["pages/**"]);
+        assert_eq!(config.exclude, vec!["legacy/**"]);
+        assert_eq!(config.extensions, Some(vec!["vue".into(), "ts".into()]));
+    }
+
+    #[test]
+    fn test_load_config_rejects_invalid_config() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join(CONFIG_FILE);
+        fs::write(&path, "exclud = []\n").expect("Failed to write config");
+        assert!(matches!(
+            Config::load(Some(&path), dir.path()),
+            Err(Error::Config { .. })
+        ));
+
+        fs::write(&path, "exclude = [\"pages/[\"]\n").expect("Failed to write config");
+        assert!(matches!(
+            Config::load(Some(&path), dir.path()),
+            Err(Error::Config { .. })
+        ));
+
+        fs::write(&path, "extensions = [\"svelte\"]\n").expect("Failed to write config");
+        assert!(matches!(
+            Config::load(Some(&path), dir.path()),
+            Err(Error::Config { .. })
+        ));
+    }
+}
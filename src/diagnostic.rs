@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::path::Path;
+
+/// ファイル内の位置を指すエラー (パースエラーなど)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    /// 1 始まりの行 (位置が分からなければ 0)
+    pub line: usize,
+    /// 1 始まりの列 (文字単位)
+    pub column: usize,
+    /// エラー位置の行の内容 (表示用)
+    #[serde(skip)]
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column,
+            source_line: String::new(),
+        }
+    }
+
+    /// ファイルの内容からエラー位置の行を取り出して保持する
+    pub fn with_source(mut self, content: &str) -> Self {
+        if self.line > 0 {
+            self.source_line = content
+                .lines()
+                .nth(self.line - 1)
+                .unwrap_or_default()
+                .to_string();
+        }
+        self
+    }
+
+    /// rustc 風にソースの抜粋付きで表示する文字列を作る
+    ///
+    /// ```text
+    /// error: failed to parse: Unexpected eof
+    ///  --> pages/index.vue:3:10
+    ///   |
+    /// 3 | const a =
+    ///   |          ^
+    /// ```
+    pub fn render(&self, path: &Path) -> String {
+        let mut out = format!("error: {}\n", self.message);
+        if self.line == 0 {
+            out += &format!(" --> {}\n", path.display());
+            return out;
+        }
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        out += &format!(
+            "{}--> {}:{}:{}\n",
+            gutter,
+            path.display(),
+            self.line,
+            self.column
+        );
+        if self.source_line.is_empty() {
+            return out;
+        }
+
+        // タブはそのまま残して、キャレットの位置がずれないようにする
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out += &format!("{} |\n", gutter);
+        out += &format!("{} | {}\n", self.line, self.source_line);
+        out += &format!("{} | {}^\n", gutter, indent);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_with_snippet() {
+        let diagnostic = Diagnostic::new("failed to parse: Unexpected eof", 2, 10)
+            .with_source("<script setup>\nconst a =\n");
+
+        assert_eq!(
+            diagnostic.render(Path::new("pages/index.vue")),
+            "error: failed to parse: Unexpected eof\n --> pages/index.vue:2:10\n  |\n2 | const a =\n  |          ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_without_location() {
+        let diagnostic = Diagnostic::new("failed to read: No such file or directory", 0, 0);
+
+        assert_eq!(
+            diagnostic.render(Path::new("a.ts")),
+            "error: failed to read: No such file or directory\n --> a.ts\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::ops::Range;
+use std::path::{Component, Path};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// 変更前後の内容から unified diff を作る
+///
+/// `color` が `true` なら端末向けに ANSI カラーで装飾する。
+pub fn unified_diff(path: &Path, original: &str, updated: &str, color: bool) -> String {
+    let name = patch_path(path);
+    let paint = |style: &str, text: &str| {
+        if color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+
+    let mut out = String::new();
+    out += &paint(BOLD, &format!("--- a/{}", name));
+    out += "\n";
+    out += &paint(BOLD, &format!("+++ b/{}", name));
+    out += "\n";
+
+    let diff = TextDiff::from_lines(original, updated);
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        out += &paint(CYAN, &hunk.header().to_string());
+        out += "\n";
+
+        for change in hunk.iter_changes() {
+            let (sign, style) = match change.tag() {
+                ChangeTag::Delete => ("-", RED),
+                ChangeTag::Insert => ("+", GREEN),
+                ChangeTag::Equal => (" ", ""),
+            };
+            let line = format!("{}{}", sign, change.value().trim_end_matches('\n'));
+            if style.is_empty() {
+                out += &line;
+            } else {
+                out += &paint(style, &line);
+            }
+            out += "\n";
+            if change.missing_newline() {
+                out += "\\ No newline at end of file\n";
+            }
+        }
+    }
+
+    out
+}
+
+/// 元の内容の `range` (バイト範囲) を `text` で置き換える編集
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// 元の内容を変更後の内容にする、重ならない行単位の編集 (前から順に並ぶ)
+pub fn edits(original: &str, updated: &str) -> Vec<Edit> {
+    let line_starts = |content: &str| {
+        let mut starts = vec![0];
+        starts.extend(content.match_indices('\n').map(|(index, _)| index + 1));
+        if starts.last() != Some(&content.len()) {
+            starts.push(content.len());
+        }
+        starts
+    };
+    let (old_starts, new_starts) = (line_starts(original), line_starts(updated));
+
+    let mut edits: Vec<Edit> = vec![];
+    for op in TextDiff::from_lines(original, updated).ops() {
+        if op.tag() == DiffTag::Equal {
+            continue;
+        }
+        let (old, new) = (op.old_range(), op.new_range());
+        let range = old_starts[old.start]..old_starts[old.end];
+        let text = &updated[new_starts[new.start]..new_starts[new.end]];
+        match edits.last_mut() {
+            // 削除と挿入が続くときは 1 つの置き換えにまとめる
+            Some(last) if last.range.end == range.start => {
+                last.range.end = range.end;
+                last.text += text;
+            }
+            _ => edits.push(Edit {
+                range,
+                text: text.to_string(),
+            }),
+        }
+    }
+    edits
+}
+
+/// `git apply` で適用できる 1 ファイル分のパッチを作る
+pub fn git_patch(path: &Path, original: &str, updated: &str) -> String {
+    let name = patch_path(path);
+    format!(
+        "diff --git a/{0} b/{0}\n{1}",
+        name,
+        unified_diff(path, original, updated, false)
+    )
+}
+
+/// パッチやレポートに書くパス
+///
+/// `./` やカレントディレクトリを除いた相対パスにし、区切り文字を `/` に揃える。
+pub fn patch_path(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let path = path
+        .strip_prefix(".")
+        .or_else(|_| path.strip_prefix(&cwd))
+        .unwrap_or(path);
+    path.components()
+        .filter_map(|component| match component {
+            Component::RootDir => Some(""),
+            component => component.as_os_str().to_str(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_without_color() {
+        let original = "const a = useState(1);\n";
+        let updated = "import { useState } from \"#imports\";\nconst a = useState(1);\n";
+
+        let diff = unified_diff(Path::new("./src/a.ts"), original, updated, false);
+
+        assert_eq!(
+            diff,
+            "--- a/src/a.ts\n+++ b/src/a.ts\n@@ -1 +1,2 @@\n+import { useState } from \"#imports\";\n const a = useState(1);\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_with_color() {
+        let diff = unified_diff(Path::new("a.ts"), "a\n", "b\n", true);
+
+        assert!(diff.contains("\x1b[31m-a\x1b[0m"));
+        assert!(diff.contains("\x1b[32m+b\x1b[0m"));
+    }
+
+    #[test]
+    fn test_git_patch_marks_missing_newline() {
+        let patch = git_patch(Path::new("pages/index.vue"), "a", "b");
+
+        assert!(patch.starts_with("diff --git a/pages/index.vue b/pages/index.vue\n"));
+        assert!(
+            patch.contains("-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n")
+        );
+    }
+
+    #[test]
+    fn test_edits_replace_changed_lines() {
+        let original = "<script setup>\nconst a = useState();\n</script>";
+        let updated =
+            "<script setup>\nimport { useState } from '#imports';\nconst a = useState();\n</script>";
+
+        let changes = edits(original, updated);
+        assert_eq!(
+            changes,
+            vec![Edit {
+                range: 15..15,
+                text: "import { useState } from '#imports';\n".into(),
+            }]
+        );
+
+        let mut applied = original.to_string();
+        for edit in changes.iter().rev() {
+            applied.replace_range(edit.range.clone(), &edit.text);
+        }
+        assert_eq!(applied, updated);
+        assert!(edits(original, original).is_empty());
+    }
+
+    #[test]
+    fn test_patch_path_relative_to_current_dir() {
+        let cwd = std::env::current_dir().unwrap();
+
+        assert_eq!(patch_path(&cwd.join("src").join("a.ts")), "src/a.ts");
+        assert_eq!(patch_path(Path::new("/outside/a.ts")), "/outside/a.ts");
+    }
+}
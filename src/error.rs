@@ -0,0 +1,96 @@
+use crate::diagnostic::Diagnostic;
+use std::io;
+use std::path::PathBuf;
+
+/// denux の処理で起こるエラー
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read: {0}")]
+    Read(#[source] io::Error),
+    #[error("failed to write: {0}")]
+    Write(#[source] io::Error),
+    /// パースエラー (位置とソースの抜粋を持つ)
+    #[error("failed to parse: {}", .0.message)]
+    Parse(Diagnostic),
+    #[error("failed to generate code: {0}")]
+    Codegen(String),
+    #[error("invalid configuration in {path:?}: {message}")]
+    Config { path: PathBuf, message: String },
+    #[error("failed to load auto-imports from {path:?}: {source}")]
+    Registry {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// `.denux/` の記録の読み書きや `undo` の失敗
+    #[error("journal error in {path:?}: {message}")]
+    Journal { path: PathBuf, message: String },
+    /// 書き換えるファイルにコミットされていない変更がある (`--allow-dirty` で無視できる)
+    #[error(
+        "{} file(s) to rewrite have uncommitted changes:\n{}\ncommit or stash them, or pass --allow-dirty",
+        .0.len(),
+        .0.iter().map(|file| format!("  * {}", file)).collect::<Vec<_>>().join("\n")
+    )]
+    DirtyWorkingTree(Vec<String>),
+    #[error("{0}")]
+    Git(String),
+    #[error("failed to watch files: {0}")]
+    Watch(String),
+    #[error("language server error: {0}")]
+    Lsp(String),
+}
+
+impl Error {
+    /// JSON などのレポートに書くエラーの種類
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Read(_) => "read",
+            Self::Write(_) => "write",
+            Self::Parse(_) => "parse",
+            Self::Codegen(_) => "codegen",
+            Self::Config { .. } => "config",
+            Self::Registry { .. } => "registry",
+            Self::Journal { .. } => "journal",
+            Self::DirtyWorkingTree(_) => "dirty-working-tree",
+            Self::Git(_) => "git",
+            Self::Watch(_) => "watch",
+            Self::Lsp(_) => "lsp",
+        }
+    }
+
+    /// 表示用の診断情報 (パースエラー以外は位置を持たない)
+    pub fn diagnostic(&self) -> Diagnostic {
+        let message = self.to_string();
+        match self {
+            Self::Parse(diagnostic) => Diagnostic {
+                message,
+                ..diagnostic.clone()
+            },
+            _ => Diagnostic::new(message, 0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_diagnostic_keeps_location() {
+        let error = Error::Parse(Diagnostic::new("Unexpected eof", 2, 10));
+        let diagnostic = error.diagnostic();
+
+        assert_eq!(error.kind(), "parse");
+        assert_eq!(diagnostic.message, "failed to parse: Unexpected eof");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 10));
+    }
+
+    #[test]
+    fn test_io_error_diagnostic_has_no_location() {
+        let error = Error::Read(io::Error::from(io::ErrorKind::NotFound));
+
+        assert_eq!(error.kind(), "read");
+        assert_eq!(error.diagnostic().line, 0);
+        assert!(error.to_string().starts_with("failed to read: "));
+    }
+}
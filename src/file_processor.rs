@@ -1,80 +1,339 @@
-use regex::Regex;
+use crate::atomic;
+use crate::error::Error;
+use crate::parser::{imported_names, script_references, ScriptBlock, ScriptLang, ScriptOutput};
+use crate::sfc::{parse_sfc, Sfc, SfcScript};
+use crate::transformer::ImportChange;
+use serde::Serialize;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 
-pub fn process_vue_file<F>(file_path: &Path, dry_run: bool, verbose: bool, process_script_setup: F)
+/// ファイルを処理しなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// `.vue` ファイルに `<script>` も `<script setup>` もない
+    NoScript,
+    /// `--include` / `--exclude` やデフォルトの除外で対象外になった
+    Excluded,
+    /// Ctrl-C で中断されたため処理しなかった
+    Interrupted,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoScript => write!(f, "no <script>"),
+            Self::Excluded => write!(f, "excluded"),
+            Self::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// 1 ファイルを処理した結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOutcome {
+    Unchanged,
+    /// 処理の対象外だった
+    Skipped(SkipReason),
+    /// 変更が必要 (dry-run では書き込まれていない)
+    Changed {
+        added_imports: Vec<ImportChange>,
+        removed_imports: Vec<ImportChange>,
+        original: String,
+        updated: String,
+    },
+}
+
+/// 1 ファイルの処理結果、または失敗の理由
+pub type FileResult = Result<FileOutcome, Error>;
+
+/// デフォルトで処理する拡張子
+///
+/// `.cjs` は CommonJS なので `import` 文を追加できず、処理しない。
+pub const DEFAULT_EXTENSIONS: &[&str] = &["vue", "js", "mjs", "jsx", "ts", "mts", "cts", "tsx"];
+
+/// 処理できる拡張子か確かめる (CLI 引数や設定ファイルの検証用)
+pub fn validate_extension(extension: &str) -> Result<String, String> {
+    let extension = extension.trim_start_matches('.');
+    if extension == "cjs" {
+        return Err("CommonJS files (.cjs) cannot use import declarations".to_string());
+    }
+    if extension == "vue" || ScriptLang::from_extension(extension).is_some() {
+        Ok(extension.to_string())
+    } else {
+        Err(format!("unsupported extension: {:?}", extension))
+    }
+}
+
+/// `.vue` ファイルの `<script>` と `<script setup>` をそれぞれ処理する
+///
+/// 通常の `<script>` を先に処理し、どちらかのブロックで import されている名前は
+/// もう一方のブロックには追加しない。
+pub fn process_vue_file<F>(file_path: &Path, dry_run: bool, process_script_setup: F) -> FileResult
 where
-    F: Fn(&str) -> String,
+    F: Fn(&ScriptBlock) -> Result<ScriptOutput, Error>,
 {
-    let content = match fs::read_to_string(file_path) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Failed to read {:?}: {}", file_path, e);
-            return;
-        }
-    };
+    let content = fs::read_to_string(file_path).map_err(Error::Read)?;
+    let outcome = process_vue_source(&content, process_script_setup)?;
+    write_changes(file_path, outcome, dry_run)
+}
 
-    let script_re = Regex::new(r"(?s)<script\s+setup[^>]*>(.*?)</script>").unwrap();
-    if let Some(captures) = script_re.captures(&content) {
-        let script_content = &captures[1];
-        let new_script_content = process_script_setup(script_content);
-
-        if script_content != new_script_content {
-            let new_content = script_re.replace(&content, |_caps: &regex::Captures| {
-                format!("<script setup>\n{}\n</script>", new_script_content)
-            });
-
-            if dry_run {
-                println!("Would update: {:?}", file_path);
-            } else if let Err(e) = fs::write(file_path, new_content.as_bytes()) {
-                eprintln!("Failed to write {:?}: {}", file_path, e);
-            } else if verbose {
-                println!("Updated: {:?}", file_path);
+/// `.vue` ファイルの内容を処理する (ファイルの読み書きはしない)
+pub fn process_vue_source<F>(content: &str, process_script_setup: F) -> FileResult
+where
+    F: Fn(&ScriptBlock) -> Result<ScriptOutput, Error>,
+{
+    let Sfc {
+        template,
+        scripts: mut blocks,
+    } = parse_sfc(content);
+    if blocks.is_empty() {
+        return Ok(FileOutcome::Skipped(SkipReason::NoScript));
+    }
+    blocks.sort_by_key(|block| block.setup);
+
+    let originals: Vec<Vec<String>> = blocks
+        .iter()
+        .map(|block| imported_names(block.content, block.lang))
+        .collect();
+    let references: Vec<Vec<String>> = blocks
+        .iter()
+        .map(|block| script_references(block.content, block.lang))
+        .collect();
+    let mut added_names: Vec<String> = vec![];
+    let mut outputs = vec![];
+    for (index, block) in blocks.iter().enumerate() {
+        let sibling_imports: Vec<String> = originals
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, names)| names.iter().cloned())
+            .chain(added_names.iter().cloned())
+            .collect();
+        let sibling_references: Vec<String> = references
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, names)| names.iter().cloned())
+            .collect();
+
+        // スクリプトブロック内の位置を .vue ファイル内の位置に直す
+        let (line_offset, column_offset) = block_offset(content, block.range.start);
+        let mut output = process_script_setup(&ScriptBlock {
+            content: block.content,
+            lang: block.lang,
+            setup: block.setup,
+            template,
+            sibling_imports: &sibling_imports,
+            sibling_references: &sibling_references,
+        })
+        .map_err(|error| match error {
+            Error::Parse(mut diagnostic) => {
+                offset_position(
+                    &mut diagnostic.line,
+                    &mut diagnostic.column,
+                    line_offset,
+                    column_offset,
+                );
+                Error::Parse(diagnostic.with_source(content))
             }
+            error => error,
+        })?;
+
+        for change in output
+            .added_imports
+            .iter_mut()
+            .chain(output.removed_imports.iter_mut())
+        {
+            offset_position(
+                &mut change.line,
+                &mut change.column,
+                line_offset,
+                column_offset,
+            );
         }
+        added_names.extend(
+            output
+                .added_imports
+                .iter()
+                .map(|change| change.name.clone()),
+        );
+        outputs.push(output);
+    }
+
+    // 後ろのブロックから置き換えて、前のブロックの位置がずれないようにする
+    // (`lang="ts"` などの属性を残すため、開始タグと終了タグは元のまま使う)
+    let mut changes: Vec<(&SfcScript, &ScriptOutput)> = blocks
+        .iter()
+        .zip(&outputs)
+        .filter(|(block, output)| block.content != output.code)
+        .collect();
+    if changes.is_empty() {
+        return Ok(FileOutcome::Unchanged);
+    }
+    changes.sort_by_key(|(block, _)| std::cmp::Reverse(block.range.start));
+    let mut new_content = content.to_string();
+    for (block, output) in changes {
+        // import を挿入しただけなら元の改行が残っているので、そのまま置き換える
+        let code = if output.code.starts_with(['\r', '\n']) {
+            output.code.clone()
+        } else {
+            format!("\n{}\n", output.code.trim_end_matches('\n'))
+        };
+        new_content.replace_range(block.range.clone(), &code);
     }
+
+    let mut output = ScriptOutput::default();
+    for block_output in outputs {
+        output.added_imports.extend(block_output.added_imports);
+        output.removed_imports.extend(block_output.removed_imports);
+    }
+    output
+        .added_imports
+        .sort_by_key(|change| (change.line, change.column));
+    output
+        .removed_imports
+        .sort_by_key(|change| (change.line, change.column));
+    Ok(changed(content, new_content, output))
 }
 
-pub fn process_ts_file<F>(file_path: &Path, dry_run: bool, verbose: bool, process_script_setup: F)
+/// ファイルから読んだのではない内容を `path` のファイルとして処理する (`.vue` かどうかは拡張子で決める)
+pub fn process_source<F>(path: &Path, content: &str, process_script_setup: F) -> FileResult
 where
-    F: Fn(&str) -> String,
+    F: Fn(&ScriptBlock) -> Result<ScriptOutput, Error>,
 {
-    let content = match fs::read_to_string(file_path) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Failed to read {:?}: {}", file_path, e);
-            return;
-        }
+    if is_vue_file(path) {
+        process_vue_source(content, process_script_setup)
+    } else {
+        process_script_source(path, content, process_script_setup)
+    }
+}
+
+pub fn is_vue_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "vue")
+}
+
+/// `.js` / `.ts` などのスクリプトファイルを処理する (構文は拡張子で決める)
+pub fn process_script_file<F>(
+    file_path: &Path,
+    dry_run: bool,
+    process_script_setup: F,
+) -> FileResult
+where
+    F: Fn(&ScriptBlock) -> Result<ScriptOutput, Error>,
+{
+    let content = fs::read_to_string(file_path).map_err(Error::Read)?;
+    let outcome = process_script_source(file_path, &content, process_script_setup)?;
+    write_changes(file_path, outcome, dry_run)
+}
+
+/// スクリプトファイルの内容を処理する (構文は `file_path` の拡張子で決め、ファイルの読み書きはしない)
+pub fn process_script_source<F>(
+    file_path: &Path,
+    content: &str,
+    process_script_setup: F,
+) -> FileResult
+where
+    F: Fn(&ScriptBlock) -> Result<ScriptOutput, Error>,
+{
+    let lang = file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ScriptLang::from_extension)
+        .unwrap_or_default();
+
+    let block = ScriptBlock {
+        content,
+        lang,
+        ..Default::default()
     };
+    let output = process_script_setup(&block).map_err(|error| match error {
+        Error::Parse(diagnostic) => Error::Parse(diagnostic.with_source(content)),
+        error => error,
+    })?;
+    if content == output.code {
+        return Ok(FileOutcome::Unchanged);
+    }
 
-    let new_content = process_script_setup(&content);
+    let new_content = output.code.clone();
+    Ok(changed(content, new_content, output))
+}
 
-    if content != new_content {
-        if dry_run {
-            println!("Would update: {:?}", file_path);
-        } else if let Err(e) = fs::write(file_path, new_content.as_bytes()) {
-            eprintln!("Failed to write {:?}: {}", file_path, e);
-        } else if verbose {
-            println!("Updated: {:?}", file_path);
+fn changed(original: &str, updated: String, output: ScriptOutput) -> FileOutcome {
+    FileOutcome::Changed {
+        added_imports: output.added_imports,
+        removed_imports: output.removed_imports,
+        original: original.to_string(),
+        updated,
+    }
+}
+
+/// dry-run でなければ変更を書き込む (結果の表示は呼び出し側で行う)
+fn write_changes(file_path: &Path, outcome: FileOutcome, dry_run: bool) -> FileResult {
+    if let FileOutcome::Changed { updated, .. } = &outcome {
+        if !dry_run {
+            atomic::write_file(file_path, updated.as_bytes()).map_err(Error::Write)?;
         }
     }
+    Ok(outcome)
+}
+
+/// `offset` バイト目が何行目 (0 始まり) の何文字目 (0 始まり) かを返す
+fn block_offset(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (line, before[line_start..].chars().count())
+}
+
+/// ブロック内の 1 始まりの行と列をファイル内の位置に直す (行が 0 なら位置なし)
+fn offset_position(line: &mut usize, column: &mut usize, line_offset: usize, column_offset: usize) {
+    if *line == 0 {
+        return;
+    }
+    if *line == 1 {
+        *column += column_offset;
+    }
+    *line += line_offset;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostic::Diagnostic;
+    use crate::registry::{Context, NuxtVersion, Registry};
+    use crate::transformer::TransformOptions;
     use std::cell::RefCell;
     use std::fs;
     use tempfile::NamedTempFile;
 
     thread_local! {
         static MOCK_CALLED: RefCell<Option<String>> = const { RefCell::new(None) };
+        static MOCK_TEMPLATE: RefCell<Option<String>> = const { RefCell::new(None) };
+        static MOCK_LANG: RefCell<Option<ScriptLang>> = const { RefCell::new(None) };
     }
 
-    fn mock_process_script_setup(input: &str) -> String {
-        let modified = format!("processed: {}", input);
-        MOCK_CALLED.with(|called| *called.borrow_mut() = Some(input.to_string()));
-        modified
+    fn mock_process_script_setup(block: &ScriptBlock) -> Result<ScriptOutput, Error> {
+        let modified = format!("processed: {}", block.content);
+        MOCK_CALLED.with(|called| *called.borrow_mut() = Some(block.content.to_string()));
+        MOCK_TEMPLATE.with(|called| *called.borrow_mut() = block.template.map(str::to_string));
+        MOCK_LANG.with(|called| *called.borrow_mut() = Some(block.lang));
+        Ok(ScriptOutput {
+            code: modified,
+            added_imports: vec![ImportChange {
+                name: "useState".into(),
+                module: "#imports".into(),
+                line: 1,
+                column: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+    }
+
+    fn mock_parse_failure(_block: &ScriptBlock) -> Result<ScriptOutput, Error> {
+        Err(Error::Parse(Diagnostic::new("Unexpected eof", 2, 10)))
     }
 
     #[test]
@@ -90,7 +349,8 @@ mod tests {
         fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
 
         MOCK_CALLED.with(|called| *called.borrow_mut() = None);
-        process_vue_file(temp_file.path(), false, false, mock_process_script_setup);
+        process_vue_file(temp_file.path(), false, mock_process_script_setup)
+            .expect("Failed to process file");
 
         MOCK_CALLED.with(|called| {
             let borrowed = called.borrow();
@@ -111,10 +371,10 @@ mod tests {
     }
 
     #[test]
-    fn test_process_vue_file_no_script_setup() {
+    fn test_process_vue_file_passes_template() {
         let vue_content = r#"
-        <template><div>Hello</div></template>
-        <script>
+        <template><NuxtLink to="/">Home</NuxtLink></template>
+        <script setup>
         const a = 42;
         </script>
         "#;
@@ -122,13 +382,37 @@ mod tests {
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
 
+        process_vue_file(temp_file.path(), true, mock_process_script_setup)
+            .expect("Failed to process file");
+
+        MOCK_TEMPLATE.with(|template| {
+            assert_eq!(
+                template.borrow().as_deref(),
+                Some(r#"<NuxtLink to="/">Home</NuxtLink>"#),
+                "process_script_setup received incorrect template"
+            );
+        });
+    }
+
+    #[test]
+    fn test_process_vue_file_no_script() {
+        let vue_content = r#"
+        <template><div>Hello</div></template>
+        <style>div { color: red; }</style>
+        "#;
+
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
+
         MOCK_CALLED.with(|called| *called.borrow_mut() = None);
-        process_vue_file(temp_file.path(), false, false, mock_process_script_setup);
+        let outcome = process_vue_file(temp_file.path(), false, mock_process_script_setup)
+            .expect("Failed to process file");
+        assert_eq!(outcome, FileOutcome::Skipped(SkipReason::NoScript));
 
         MOCK_CALLED.with(|called| {
             assert!(
                 called.borrow().is_none(),
-                "process_script_setup should not be called when there is no <script setup>"
+                "process_script_setup should not be called when there is no <script>"
             );
         });
 
@@ -136,19 +420,147 @@ mod tests {
             fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
         assert_eq!(
             result_content, vue_content,
-            "The file should remain unchanged when there is no <script setup>"
+            "The file should remain unchanged when there is no <script>"
         );
     }
 
     #[test]
-    fn test_process_ts_file_calls_process_script_setup() {
+    fn test_process_vue_file_options_api_script() {
+        let vue_content = "<template><div /></template>\n<script lang=\"ts\">\nexport default defineNuxtComponent({});\n</script>\n";
+
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
+
+        let setup = RefCell::new(None);
+        let status = process_vue_file(temp_file.path(), true, |block: &ScriptBlock| {
+            *setup.borrow_mut() = Some(block.setup);
+            mock_process_script_setup(block)
+        })
+        .expect("Failed to process file");
+
+        assert_eq!(*setup.borrow(), Some(false));
+        let FileOutcome::Changed { updated, .. } = status else {
+            panic!("The file should need changes");
+        };
+        assert_eq!(
+            updated,
+            "<template><div /></template>\n<script lang=\"ts\">\nprocessed: \nexport default defineNuxtComponent({});\n</script>\n"
+        );
+    }
+
+    #[test]
+    fn test_process_vue_file_processes_each_script_block() {
+        let vue_content = r#"<script lang="ts" setup>
+const route = useRoute();
+const count = ref(0);
+</script>
+
+<script lang="ts">
+import { ref } from 'vue';
+export default defineNuxtComponent({ name: 'Counter' });
+export const useCounter = () => ref(0);
+</script>
+"#;
+
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
+
+        let calls = RefCell::new(vec![]);
+        let status = process_vue_file(temp_file.path(), true, |block: &ScriptBlock| {
+            calls
+                .borrow_mut()
+                .push((block.setup, block.sibling_imports.to_vec()));
+            let added = if block.setup {
+                "useRoute"
+            } else {
+                "defineNuxtComponent"
+            };
+            Ok(ScriptOutput {
+                code: format!("import {{ {} }} from '#imports';{}", added, block.content),
+                added_imports: vec![ImportChange {
+                    name: added.into(),
+                    module: "#imports".into(),
+                    line: 2,
+                    column: 1,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+        })
+        .expect("Failed to process file");
+
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                (false, vec![]),
+                (
+                    true,
+                    vec!["ref".to_string(), "defineNuxtComponent".to_string()]
+                ),
+            ],
+            "The plain <script> should be processed first and its imports shared"
+        );
+
+        let FileOutcome::Changed {
+            added_imports,
+            updated,
+            ..
+        } = status
+        else {
+            panic!("The file should need changes");
+        };
+        assert_eq!(
+            added_imports
+                .iter()
+                .map(|change| (change.name.as_str(), change.line))
+                .collect::<Vec<_>>(),
+            vec![("useRoute", 2), ("defineNuxtComponent", 7)]
+        );
+        assert!(updated.starts_with(
+            "<script lang=\"ts\" setup>\nimport { useRoute } from '#imports';\nconst route"
+        ));
+        assert!(updated.contains(
+            "const count = ref(0);\n</script>\n\n<script lang=\"ts\">\nimport { defineNuxtComponent } from '#imports';\nimport { ref }"
+        ));
+    }
+
+    #[test]
+    fn test_process_vue_source_keeps_imports_used_by_other_blocks() {
+        let vue_content = r#"<script lang="ts">
+import { ref } from 'vue';
+export default {};
+</script>
+<script setup>
+const count = ref(0);
+</script>
+"#;
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            remove_unused: true,
+            ..Default::default()
+        };
+
+        let status = process_vue_source(vue_content, |block: &ScriptBlock| {
+            crate::parser::process_script_setup(block, &registry, options)
+        })
+        .expect("Failed to process source");
+        assert!(
+            matches!(status, FileOutcome::Unchanged),
+            "`ref` is used in <script setup> and should be kept: {:?}",
+            status
+        );
+    }
+
+    #[test]
+    fn test_process_script_file_calls_process_script_setup() {
         let ts_content = "const a = 42;";
 
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         fs::write(temp_file.path(), ts_content).expect("Failed to write to temp file");
 
         MOCK_CALLED.with(|called| *called.borrow_mut() = None);
-        process_ts_file(temp_file.path(), false, false, mock_process_script_setup);
+        process_script_file(temp_file.path(), false, mock_process_script_setup)
+            .expect("Failed to process file");
 
         MOCK_CALLED.with(|called| {
             let borrowed = called.borrow();
@@ -180,7 +592,8 @@ mod tests {
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
 
-        process_vue_file(temp_file.path(), true, false, mock_process_script_setup);
+        process_vue_file(temp_file.path(), true, mock_process_script_setup)
+            .expect("Failed to process file");
 
         let result_content =
             fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
@@ -191,13 +604,14 @@ mod tests {
     }
 
     #[test]
-    fn test_process_ts_file_dry_run() {
+    fn test_process_script_file_dry_run() {
         let ts_content = "let y = 20;";
 
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         fs::write(temp_file.path(), ts_content).expect("Failed to write to temp file");
 
-        process_ts_file(temp_file.path(), true, false, mock_process_script_setup);
+        process_script_file(temp_file.path(), true, mock_process_script_setup)
+            .expect("Failed to process file");
 
         let result_content =
             fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
@@ -208,41 +622,122 @@ mod tests {
     }
 
     #[test]
-    fn test_process_vue_file_verbose_mode() {
-        let vue_content = r#"
-        <template><div>Hello</div></template>
-        <script setup>
-        let y = 99;
-        </script>
-        "#;
-
+    fn test_process_script_file_reports_status() {
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
-        fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
+        fs::write(temp_file.path(), "const a = 1;").expect("Failed to write to temp file");
 
-        process_vue_file(temp_file.path(), false, true, mock_process_script_setup);
+        let status =
+            process_script_file(temp_file.path(), true, mock_process_script_setup).unwrap();
+        assert_eq!(
+            status,
+            FileOutcome::Changed {
+                added_imports: vec![ImportChange {
+                    name: "useState".into(),
+                    module: "#imports".into(),
+                    line: 1,
+                    column: 1,
+                    ..Default::default()
+                }],
+                removed_imports: vec![],
+                original: "const a = 1;".into(),
+                updated: "processed: const a = 1;".into(),
+            }
+        );
 
-        let result_content =
-            fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
-        assert!(
-            result_content.contains("processed:"),
-            "Verbose mode should still apply changes"
+        let unchanged = process_script_file(temp_file.path(), true, |block: &ScriptBlock| {
+            Ok(ScriptOutput {
+                code: block.content.to_string(),
+                ..Default::default()
+            })
+        })
+        .unwrap();
+        assert_eq!(unchanged, FileOutcome::Unchanged);
+    }
+
+    #[test]
+    fn test_process_files_report_failures() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        fs::write(temp_file.path(), "<script setup>\nconst a =\n</script>")
+            .expect("Failed to write to temp file");
+
+        let result = process_vue_file(temp_file.path(), false, mock_parse_failure);
+        let Err(Error::Parse(diagnostic)) = result else {
+            panic!("The parse error should be returned");
+        };
+        assert_eq!(
+            diagnostic,
+            Diagnostic {
+                message: "Unexpected eof".into(),
+                line: 2,
+                column: 10,
+                source_line: "const a =".into(),
+            }
         );
+
+        let missing = temp_file.path().with_extension("missing");
+        let result = process_script_file(&missing, false, mock_process_script_setup);
+        assert!(matches!(result, Err(Error::Read(_))));
     }
 
     #[test]
-    fn test_process_ts_file_verbose_mode() {
-        let ts_content = "let z = 5;";
+    fn test_process_vue_file_offsets_locations() {
+        let vue_content =
+            "<template><div /></template>\n<script setup>\nconst a = 42;\n</script>\n";
 
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
-        fs::write(temp_file.path(), ts_content).expect("Failed to write to temp file");
+        fs::write(temp_file.path(), vue_content).expect("Failed to write to temp file");
 
-        process_ts_file(temp_file.path(), false, true, mock_process_script_setup);
+        let status = process_vue_file(temp_file.path(), true, mock_process_script_setup);
+        let Ok(FileOutcome::Changed { added_imports, .. }) = status else {
+            panic!("The file should need changes");
+        };
+        assert_eq!(
+            (added_imports[0].line, added_imports[0].column),
+            (2, 15),
+            "Locations should be relative to the .vue file"
+        );
+    }
 
-        let result_content =
-            fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
+    #[test]
+    fn test_process_files_pass_lang() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let vue_file = temp_dir.path().join("index.vue");
+        fs::write(
+            &vue_file,
+            "<script setup lang=\"ts\">\nconst a = 42;\n</script>\n",
+        )
+        .expect("Failed to write to temp file");
+
+        let status = process_vue_file(&vue_file, true, mock_process_script_setup)
+            .expect("Failed to process file");
+        MOCK_LANG.with(|lang| assert_eq!(*lang.borrow(), Some(ScriptLang::Ts)));
+        let FileOutcome::Changed { updated, .. } = status else {
+            panic!("The file should need changes");
+        };
         assert!(
-            result_content.contains("processed:"),
-            "Verbose mode should still apply changes"
+            updated.starts_with("<script setup lang=\"ts\">\n"),
+            "The attributes of <script setup> should be kept"
         );
+
+        for (name, expected) in [
+            ("a.mjs", ScriptLang::Js),
+            ("a.jsx", ScriptLang::Jsx),
+            ("a.cts", ScriptLang::Ts),
+            ("a.tsx", ScriptLang::Tsx),
+        ] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "const a = 42;").expect("Failed to write to temp file");
+            process_script_file(&file, true, mock_process_script_setup)
+                .expect("Failed to process file");
+            MOCK_LANG.with(|lang| assert_eq!(*lang.borrow(), Some(expected), "{}", name));
+        }
+    }
+
+    #[test]
+    fn test_validate_extension() {
+        assert_eq!(validate_extension(".mts"), Ok("mts".to_string()));
+        assert_eq!(validate_extension("vue"), Ok("vue".to_string()));
+        assert!(validate_extension("svelte").is_err());
+        assert!(validate_extension("cjs").is_err());
     }
 }
@@ -0,0 +1,222 @@
+use crate::file_processor::DEFAULT_EXTENSIONS;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{Walk, WalkBuilder};
+use std::path::Path;
+use std::sync::Arc;
+
+/// `.gitignore` と同じ書式で denux だけが無視するファイルを書く
+pub const IGNORE_FILE: &str = ".denuxignore";
+
+/// デフォルトで対象から外す、依存パッケージや生成物のディレクトリとファイル
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "**/.git",
+    "**/.denux",
+    "**/node_modules",
+    "**/.nuxt",
+    "**/.output",
+    "**/dist",
+    "**/*.d.ts",
+    "**/*.d.mts",
+    "**/*.d.cts",
+    "**/nuxt.config.ts",
+];
+
+/// 拡張子と include / exclude の glob で対象ファイルを絞り込む
+///
+/// glob は対象ディレクトリからの相対パスに対して評価する。`*` は `/` をまたがない。
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    extensions: Vec<String>,
+}
+
+impl PathFilter {
+    /// `exclude` は [`DEFAULT_EXCLUDES`] に追加される
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_set(include.iter().map(String::as_str))?)
+        };
+        let exclude = build_set(
+            DEFAULT_EXCLUDES
+                .iter()
+                .copied()
+                .chain(exclude.iter().map(String::as_str)),
+        )?;
+
+        Ok(Self {
+            include,
+            exclude,
+            extensions: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        })
+    }
+
+    /// 処理する拡張子を [`DEFAULT_EXTENSIONS`] から置き換える
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// 処理する拡張子のファイルか
+    pub fn has_target_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|target| target == ext))
+    }
+
+    /// ディレクトリやファイルが除外されているか
+    ///
+    /// `dist/**` のように中身を指定した glob は、ディレクトリそのものも除外する。
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        self.exclude.is_match(relative) || self.exclude.is_match(relative.join("_"))
+    }
+
+    /// 親ディレクトリが除外されていないかも含めて、ファイルが対象になるか
+    ///
+    /// 走査では除外されたディレクトリに入らないので、走査を経ずに得たパス (ファイルの監視など) に使う。
+    pub fn is_included_with_parents(&self, relative: &Path) -> bool {
+        self.is_included(relative)
+            && !relative
+                .ancestors()
+                .skip(1)
+                .any(|dir| !dir.as_os_str().is_empty() && self.is_excluded(dir))
+    }
+
+    /// ファイルが対象になるか
+    pub fn is_included(&self, relative: &Path) -> bool {
+        !self.is_excluded(relative)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+}
+
+/// `.gitignore` / `.denuxignore` と除外の glob に従って `target` を走査する
+///
+/// 除外されたディレクトリは中に入らない。ファイルの include / exclude は呼び出し側で
+/// [`PathFilter::is_included`] を使って判定する (スキップした理由を残すため)。
+pub fn walk(target: &Path, filter: Arc<PathFilter>) -> Walk {
+    let root = target.to_path_buf();
+    WalkBuilder::new(target)
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .filter_entry(move |entry| {
+            let is_dir = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir());
+            !is_dir || !filter.is_excluded(relative_path(entry.path(), &root))
+        })
+        .build()
+}
+
+/// 対象ディレクトリからの相対パス
+pub fn relative_path<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// glob として解釈できるか確かめる (CLI 引数や設定ファイルの検証用)
+pub fn validate_glob(pattern: &str) -> Result<String, String> {
+    glob(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn build_set<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(glob(pattern)?);
+    }
+    builder.build()
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_default_excludes() {
+        let filter = PathFilter::new(&[], &[]).unwrap();
+
+        assert!(filter.is_excluded(Path::new("node_modules")));
+        assert!(filter.is_excluded(Path::new("app/.nuxt")));
+        assert!(!filter.is_included(Path::new("types/env.d.ts")));
+        assert!(!filter.is_included(Path::new("types/env.d.mts")));
+        assert!(!filter.is_included(Path::new("types/env.d.cts")));
+        assert!(!filter.is_included(Path::new("nuxt.config.ts")));
+        assert!(filter.is_included(Path::new("pages/index.vue")));
+        assert!(filter.is_included(Path::new("distance.ts")));
+    }
+
+    #[test]
+    fn test_target_extensions() {
+        let filter = PathFilter::new(&[], &[]).unwrap();
+        assert!(filter.has_target_extension(Path::new("server/api/hello.mjs")));
+        assert!(filter.has_target_extension(Path::new("components/Card.tsx")));
+        assert!(!filter.has_target_extension(Path::new("assets/main.css")));
+
+        let filter = filter.with_extensions(vec!["vue".into()]);
+        assert!(filter.has_target_extension(Path::new("pages/index.vue")));
+        assert!(!filter.has_target_extension(Path::new("utils/format.ts")));
+    }
+
+    #[test]
+    fn test_include_and_exclude_globs() {
+        let filter = PathFilter::new(&["pages/**".into()], &["pages/legacy/**".into()]).unwrap();
+
+        assert!(filter.is_included(Path::new("pages/index.vue")));
+        assert!(!filter.is_included(Path::new("components/Foo.vue")));
+        assert!(filter.is_excluded(Path::new("pages/legacy")));
+        assert!(!filter.is_included(Path::new("pages/legacy/old.vue")));
+        assert!(filter.is_included(Path::new("pages/node_modules/pkg/index.vue")));
+        assert!(!filter.is_included_with_parents(Path::new("pages/node_modules/pkg/index.vue")));
+        assert!(filter.is_included_with_parents(Path::new("pages/index.vue")));
+    }
+
+    #[test]
+    fn test_walk_respects_ignore_files_and_excludes() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        for file in [
+            "pages/index.vue",
+            "pages/generated.ts",
+            "legacy/old.ts",
+            "node_modules/pkg/index.ts",
+            ".nuxt/imports.d.ts",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::write(dir.path().join(".gitignore"), "generated.ts\n").unwrap();
+        fs::write(dir.path().join(IGNORE_FILE), "legacy/\n").unwrap();
+
+        let filter = Arc::new(PathFilter::new(&[], &[]).unwrap());
+        let mut files: Vec<_> = walk(dir.path(), filter)
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(|entry| relative_path(entry.path(), dir.path()).to_path_buf())
+            .collect();
+        files.sort();
+
+        assert_eq!(
+            files,
+            vec![
+                Path::new(".denuxignore"),
+                Path::new(".gitignore"),
+                Path::new("pages/index.vue"),
+            ]
+        );
+    }
+}
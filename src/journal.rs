@@ -0,0 +1,234 @@
+use crate::atomic;
+use crate::error::Error;
+use crate::filter::relative_path;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 直前の実行の記録を置くディレクトリ (対象ディレクトリの直下)
+pub const JOURNAL_DIR: &str = ".denux";
+const JOURNAL_FILE: &str = "journal.ndjson";
+const BACKUP_DIR: &str = "backups";
+
+/// 書き換えたファイル 1 つの記録
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// 対象ディレクトリからの相対パス
+    pub path: PathBuf,
+    /// `backups/` 内の元の内容のファイル名
+    pub backup: String,
+    pub original_hash: String,
+    pub updated_hash: String,
+}
+
+/// 書き換える前に元の内容をバックアップし、実行ごとに記録を残す
+///
+/// 記録は最初にファイルを書き換えるときに作り直すので、何も変えなかった実行では
+/// 前回の記録が残る。
+pub struct Journal {
+    root: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            file: Mutex::new(None),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.root.join(JOURNAL_DIR)
+    }
+
+    /// 元の内容をバックアップして記録してから `path` を `updated` に書き換える
+    pub fn write(&self, path: &Path, original: &str, updated: &str) -> Result<(), Error> {
+        {
+            let mut file = self.file.lock().unwrap();
+            if file.is_none() {
+                *file = Some(self.start()?);
+            }
+            let journal = file.as_mut().unwrap();
+
+            let relative = relative_path(path, &self.root).to_path_buf();
+            let entry = JournalEntry {
+                backup: backup_name(&relative),
+                path: relative,
+                original_hash: hash(original),
+                updated_hash: hash(updated),
+            };
+            let backup = self.dir().join(BACKUP_DIR).join(&entry.backup);
+            fs::write(&backup, original).map_err(|e| self.error(e))?;
+            let line = serde_json::to_string(&entry).expect("journal entries are serializable");
+            writeln!(journal, "{}", line)
+                .and_then(|_| journal.sync_data())
+                .map_err(|e| self.error(e))?;
+        }
+
+        atomic::write_file(path, updated.as_bytes()).map_err(Error::Write)
+    }
+
+    /// 前回の記録を消して新しい記録を始める
+    fn start(&self) -> Result<File, Error> {
+        let dir = self.dir();
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|e| self.error(e))?;
+        }
+        fs::create_dir_all(dir.join(BACKUP_DIR)).map_err(|e| self.error(e))?;
+        // バックアップを git で追跡しないようにする
+        fs::write(dir.join(".gitignore"), "*\n").map_err(|e| self.error(e))?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))
+            .map_err(|e| self.error(e))
+    }
+
+    fn error(&self, error: impl ToString) -> Error {
+        Error::Journal {
+            path: self.dir(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// 直前の実行で書き換えたファイルを元に戻し、戻したファイルを返す
+///
+/// その後に変更されたファイルが 1 つでもあれば、何も戻さずにエラーにする。
+pub fn undo(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let journal = Journal::new(root);
+    let dir = journal.dir();
+    let content = match fs::read_to_string(dir.join(JOURNAL_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(journal.error("no previous run to undo"))
+        }
+        Err(e) => return Err(journal.error(e)),
+    };
+    let entries = content
+        .lines()
+        .map(serde_json::from_str::<JournalEntry>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| journal.error(e))?;
+
+    let changed: Vec<String> = entries
+        .iter()
+        .filter(|entry| {
+            fs::read_to_string(root.join(&entry.path))
+                .map_or(true, |current| hash(&current) != entry.updated_hash)
+        })
+        .map(|entry| entry.path.display().to_string())
+        .collect();
+    if !changed.is_empty() {
+        return Err(journal.error(format!(
+            "refusing to undo, file(s) changed since the last run: {}",
+            changed.join(", ")
+        )));
+    }
+
+    let mut restored = vec![];
+    for entry in &entries {
+        let backup = dir.join(BACKUP_DIR).join(&entry.backup);
+        let original = fs::read(&backup).map_err(|e| journal.error(e))?;
+        let path = root.join(&entry.path);
+        atomic::write_file(&path, &original).map_err(Error::Write)?;
+        restored.push(path);
+    }
+    fs::remove_dir_all(&dir).map_err(|e| journal.error(e))?;
+    Ok(restored)
+}
+
+/// 内容の SHA-256 (16 進数)
+fn hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// 相対パスからバックアップのファイル名を作る (`pages/index.vue` → `pages%index.vue`)
+fn backup_name(relative: &Path) -> String {
+    relative
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace(['/', '\\'], "%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_undo_restores_last_run() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let page = dir.path().join("pages/index.vue");
+        fs::create_dir_all(page.parent().unwrap()).unwrap();
+        fs::write(&page, "<script setup>\nuseRoute();\n</script>\n").unwrap();
+
+        let journal = Journal::new(dir.path());
+        journal
+            .write(
+                &page,
+                "<script setup>\nuseRoute();\n</script>\n",
+                "<script setup>\nimport { useRoute } from '#imports';\nuseRoute();\n</script>\n",
+            )
+            .expect("Failed to write file");
+        assert!(fs::read_to_string(&page).unwrap().contains("import"));
+        assert!(dir
+            .path()
+            .join(JOURNAL_DIR)
+            .join(BACKUP_DIR)
+            .join("pages%index.vue")
+            .is_file());
+
+        let restored = undo(dir.path()).expect("Failed to undo");
+        assert_eq!(restored, vec![page.clone()]);
+        assert_eq!(
+            fs::read_to_string(&page).unwrap(),
+            "<script setup>\nuseRoute();\n</script>\n"
+        );
+        assert!(
+            undo(dir.path()).is_err(),
+            "The same run should not be undone twice"
+        );
+    }
+
+    #[test]
+    fn test_undo_refuses_changed_files() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let [a, b] = ["a.ts", "b.ts"].map(|name| dir.path().join(name));
+        fs::write(&a, "useState();\n").unwrap();
+        fs::write(&b, "useFetch();\n").unwrap();
+
+        let journal = Journal::new(dir.path());
+        journal
+            .write(
+                &a,
+                "useState();\n",
+                "import { useState } from '#imports';\nuseState();\n",
+            )
+            .unwrap();
+        journal
+            .write(
+                &b,
+                "useFetch();\n",
+                "import { useFetch } from '#imports';\nuseFetch();\n",
+            )
+            .unwrap();
+        fs::write(&b, "// edited by hand\n").unwrap();
+
+        let Err(Error::Journal { message, .. }) = undo(dir.path()) else {
+            panic!("Undo should be refused");
+        };
+        assert!(message.contains("b.ts"), "{}", message);
+        assert!(
+            fs::read_to_string(&a).unwrap().contains("import"),
+            "No file should be restored when the undo is refused"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Nuxt の自動インポートに頼っているコードへ明示的な import を追加する
+//!
+//! ビルドツールなどから使うときは、ルートの [`transform_source`]、[`Registry`] と
+//! [`RegistrySet`]、SFC のパーサー [`parse_sfc`] を使う。モジュールの中身は CLI
+//! (`nuxt-auto-import-replacer`) のためのもので、互換性は保証しない。
+//!
+//! ```no_run
+//! use denux::{transform_source, NuxtVersion, Options, RegistrySet};
+//! use std::path::Path;
+//!
+//! let root = Path::new("my-nuxt-app");
+//! let registries = RegistrySet::load(NuxtVersion::LATEST, root)?;
+//! let path = root.join("pages/index.vue");
+//! let source = std::fs::read_to_string(&path)?;
+//! let options = Options {
+//!     root: root.to_path_buf(),
+//!     ..Default::default()
+//! };
+//!
+//! let result = transform_source(&source, &path, &options, registries.for_path(&path, root));
+//! for change in &result.added_imports {
+//!     println!("{}:{}: import {} from {}", change.line, change.column, change.name, change.module);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod atomic;
+pub mod config;
+pub mod diagnostic;
+pub mod diff;
+pub mod error;
+pub mod file_processor;
+pub mod filter;
+pub mod journal;
+pub mod parser;
+pub mod registry;
+pub mod report;
+pub mod sfc;
+pub mod template;
+pub mod transformer;
+pub mod vcs;
+
+pub use diagnostic::Diagnostic;
+pub use diff::Edit;
+pub use error::Error;
+pub use parser::ScriptLang;
+pub use registry::{
+    Context, ImportKind, ImportStyle, MacroScope, NuxtVersion, Registry, RegistryEntry, RegistrySet,
+};
+pub use sfc::{parse_sfc, Sfc, SfcScript};
+pub use transformer::ImportChange;
+
+use file_processor::{process_source, FileOutcome};
+use parser::{process_script_setup, ScriptBlock, ScriptOutput};
+use registry::{is_page_path, ScriptScope};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use transformer::TransformOptions;
+
+/// [`transform_source`] の設定
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// プロジェクトルート (`pages/` 以下のファイルかどうかをここからの相対パスで判定する)
+    pub root: PathBuf,
+    pub style: ImportStyle,
+    /// 明示的な import を追加する代わりに、自動インポートされる名前の import を取り除く
+    pub reverse: bool,
+    /// 使われていない自動インポート由来の import を取り除く
+    pub remove_unused: bool,
+}
+
+/// [`transform_source`] の結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformResult {
+    /// 変換後の内容 (変更がないときや失敗したときは元の内容)
+    pub code: String,
+    /// 元の内容を `code` にする編集 (変更がなければ空)
+    pub edits: Vec<Edit>,
+    pub added_imports: Vec<ImportChange>,
+    pub removed_imports: Vec<ImportChange>,
+    /// 変換できなかった理由 (パースエラーなど)、使える場所の外で呼ばれたコンパイラマクロ、
+    /// `ImportStyle::Source` でも仮想モジュールから import したコンポーネント
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TransformResult {
+    pub fn is_changed(&self) -> bool {
+        !self.edits.is_empty()
+    }
+}
+
+/// `path` のファイルの内容として `source` を変換する (ファイルの読み書きはしない)
+///
+/// `.vue` かどうかと構文は `path` の拡張子で決める。`registry` には
+/// [`RegistrySet::for_path`] などで `path` に合ったものを渡す。
+pub fn transform_source(
+    source: &str,
+    path: &Path,
+    options: &Options,
+    registry: &Registry,
+) -> TransformResult {
+    let misused_macros = RefCell::new(vec![]);
+    let virtual_imports = RefCell::new(vec![]);
+    let processor = script_processor(path, options, registry);
+    let outcome = process_source(path, source, |block: &ScriptBlock| {
+        let output = processor(block)?;
+        misused_macros
+            .borrow_mut()
+            .extend(output.misused_macros.iter().cloned());
+        virtual_imports
+            .borrow_mut()
+            .extend(output.virtual_imports.iter().cloned());
+        Ok(output)
+    });
+
+    let mut result = TransformResult {
+        code: source.to_string(),
+        ..Default::default()
+    };
+    match outcome {
+        Ok(FileOutcome::Changed {
+            added_imports,
+            removed_imports,
+            updated,
+            ..
+        }) => {
+            result.edits = diff::edits(source, &updated);
+            result.code = updated;
+            result.added_imports = added_imports;
+            result.removed_imports = removed_imports;
+        }
+        Ok(FileOutcome::Unchanged | FileOutcome::Skipped(_)) => {}
+        Err(error) => result.diagnostics.push(error.diagnostic()),
+    }
+    result
+        .diagnostics
+        .extend(misused_macros.into_inner().into_iter().map(|name| {
+            Diagnostic::new(
+                format!("compiler macro `{}` is not available in this file", name),
+                0,
+                0,
+            )
+        }));
+    result
+        .diagnostics
+        .extend(virtual_imports.into_inner().into_iter().map(|name| {
+            Diagnostic::new(
+                format!(
+                    "`{}` has no public source module, imported from #components instead",
+                    name
+                ),
+                0,
+                0,
+            )
+        }));
+    result
+}
+
+/// `path` の場所 (ページかどうか) に合わせてスクリプトブロックを変換する関数を作る
+///
+/// [`file_processor`] の関数に渡して使う。
+pub fn script_processor<'a>(
+    path: &Path,
+    options: &'a Options,
+    registry: &'a Registry,
+) -> impl Fn(&ScriptBlock<'_>) -> Result<ScriptOutput, Error> + 'a {
+    let page = is_page_path(path, &options.root);
+
+    move |block: &ScriptBlock| {
+        let options = TransformOptions {
+            scope: ScriptScope {
+                setup: block.setup,
+                page,
+            },
+            style: options.style,
+            reverse: options.reverse,
+            remove_unused: options.remove_unused,
+        };
+        process_script_setup(block, registry, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform_source() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = Options {
+            root: PathBuf::from("app"),
+            ..Default::default()
+        };
+        let source = "<template>\n  <div />\n</template>\n<script setup>\nconst route = useRoute();\ndefinePageMeta({});\n</script>\n";
+
+        let result = transform_source(
+            source,
+            Path::new("app/pages/index.vue"),
+            &options,
+            &registry,
+        );
+        assert!(result.is_changed());
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        let names: Vec<&str> = result
+            .added_imports
+            .iter()
+            .map(|change| change.name.as_str())
+            .collect();
+        assert_eq!(names, ["useRoute"], "compiler macros are never imported");
+        assert_eq!(
+            (result.added_imports[0].line, result.added_imports[0].column),
+            (5, 15)
+        );
+        let mut applied = source.to_string();
+        for edit in result.edits.iter().rev() {
+            applied.replace_range(edit.range.clone(), &edit.text);
+        }
+        assert_eq!(applied, result.code);
+
+        let result = transform_source(
+            source,
+            Path::new("app/components/Card.vue"),
+            &options,
+            &registry,
+        );
+        assert_eq!(result.diagnostics.len(), 1, "definePageMeta outside pages/");
+        assert!(result.diagnostics[0].message.contains("definePageMeta"));
+    }
+
+    #[test]
+    fn test_transform_source_reports_parse_errors() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let source = "const route = useRoute(\n";
+
+        let result = transform_source(source, Path::new("a.ts"), &Options::default(), &registry);
+        assert!(!result.is_changed());
+        assert_eq!(result.code, source);
+        assert_eq!(result.diagnostics.len(), 1);
+        assert!(result.diagnostics[0].message.starts_with("failed to parse"));
+        assert_eq!(result.diagnostics[0].line, 1);
+    }
+}
@@ -0,0 +1,525 @@
+use crate::cli::Cli;
+use crate::{load_filter, load_registries, EXIT_FAILURE, INTERRUPTED};
+use denux::filter::{relative_path, PathFilter};
+use denux::report::{missing_import_message, MISSING_IMPORT_RULE};
+use denux::{transform_source, Error, ImportChange, Options, Registry, RegistrySet};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, Request as _};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    NumberOrString, Position, PublishDiagnosticsParams, Range, SaveOptions, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Ctrl-C を確かめる間隔
+const POLL: Duration = Duration::from_millis(200);
+/// 診断の `source`
+const SOURCE: &str = "denux";
+
+/// `lsp`: 標準入出力で Language Server Protocol のサーバーを動かす
+///
+/// 開いている `.vue` / スクリプトのバッファについて、明示的な import がない自動インポートの
+/// 名前を診断として送り、名前 1 つまたはファイル全体に import を追加するクイックフィックスを返す。
+/// ファイルは書き換えない (編集はエディタが適用する)。
+pub fn run(args: &Cli) -> ExitCode {
+    match serve(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn serve(args: &Cli) -> Result<(), Error> {
+    let mut server = Server::new(args)?;
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                    include_text: Some(false),
+                })),
+                ..Default::default()
+            },
+        )),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::SOURCE_FIX_ALL,
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    connection
+        .initialize(serde_json::to_value(capabilities).expect("capabilities are serializable"))
+        .map_err(|e| Error::Lsp(e.to_string()))?;
+
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        let message = match connection.receiver.recv_timeout(POLL) {
+            Ok(message) => message,
+            Err(e) if e.is_timeout() => continue,
+            Err(_) => break,
+        };
+        let replies = match message {
+            Message::Request(request) => {
+                if connection
+                    .handle_shutdown(&request)
+                    .map_err(|e| Error::Lsp(e.to_string()))?
+                {
+                    break;
+                }
+                vec![Message::Response(server.handle_request(request))]
+            }
+            Message::Notification(notification) => server
+                .handle_notification(notification)
+                .into_iter()
+                .map(Message::Notification)
+                .collect(),
+            Message::Response(_) => vec![],
+        };
+        for reply in replies {
+            connection
+                .sender
+                .send(reply)
+                .map_err(|e| Error::Lsp(e.to_string()))?;
+        }
+    }
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        // 標準入力を読むスレッドは終わらないので待たない
+        return Ok(());
+    }
+    drop(connection);
+    io_threads.join().map_err(|e| Error::Lsp(e.to_string()))
+}
+
+/// 開いているドキュメントとレジストリを持つサーバーの状態
+struct Server<'a> {
+    args: &'a Cli,
+    /// 対象ディレクトリ (エディタから届くパスと比べるため絶対パス)
+    root: PathBuf,
+    filter: Arc<PathFilter>,
+    /// `--reverse` や `--remove-unused` にかかわらず、足りない import の追加だけを行う
+    options: Options,
+    registries: RegistrySet,
+    documents: HashMap<Url, String>,
+}
+
+impl<'a> Server<'a> {
+    fn new(args: &'a Cli) -> Result<Self, Error> {
+        let filter = load_filter(args)?;
+        let (_, registries) = load_registries(args)?;
+        let root = std::path::absolute(&args.target).map_err(Error::Read)?;
+        let options = Options {
+            root: root.clone(),
+            style: args.import_style,
+            ..Default::default()
+        };
+        Ok(Self {
+            args,
+            root,
+            filter,
+            options,
+            registries,
+            documents: HashMap::new(),
+        })
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            CodeActionRequest::METHOD => {
+                match serde_json::from_value::<CodeActionParams>(request.params) {
+                    Ok(params) => Response::new_ok(request.id, self.code_actions(&params)),
+                    Err(e) => Response::new_err(
+                        request.id,
+                        ErrorCode::InvalidParams as i32,
+                        e.to_string(),
+                    ),
+                }
+            }
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request: {}", method),
+            ),
+        }
+    }
+
+    /// ドキュメントの変更を反映し、送る診断を返す
+    fn handle_notification(&mut self, notification: Notification) -> Vec<Notification> {
+        let params = notification.params;
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) else {
+                    return vec![];
+                };
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                vec![self.publish(&uri)]
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidChangeTextDocumentParams>(params)
+                else {
+                    return vec![];
+                };
+                // 同期は FULL なので最後の変更が内容全体
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return vec![];
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                vec![self.publish(&uri)]
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(params)
+                else {
+                    return vec![];
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(uri, vec![])]
+            }
+            DidSaveTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidSaveTextDocumentParams>(params) else {
+                    return vec![];
+                };
+                self.reload_if_input(&params.text_document.uri)
+            }
+            _ => vec![],
+        }
+    }
+
+    /// `composables/` などが保存されたらレジストリを作り直し、開いているドキュメントを診断し直す
+    fn reload_if_input(&mut self, uri: &Url) -> Vec<Notification> {
+        let Ok(path) = uri.to_file_path() else {
+            return vec![];
+        };
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return vec![];
+        };
+        if !RegistrySet::is_input(relative) {
+            return vec![];
+        }
+        match load_registries(self.args) {
+            Ok((_, registries)) => self.registries = registries,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return vec![];
+            }
+        }
+        let mut uris: Vec<Url> = self.documents.keys().cloned().collect();
+        uris.sort();
+        uris.iter().map(|uri| self.publish(uri)).collect()
+    }
+
+    fn publish(&self, uri: &Url) -> Notification {
+        let diagnostics = match self.documents.get(uri) {
+            Some(text) => self
+                .missing_imports(uri, text)
+                .iter()
+                .map(|change| diagnostic(text, change))
+                .collect(),
+            None => vec![],
+        };
+        publish_diagnostics(uri.clone(), diagnostics)
+    }
+
+    /// 明示的な import がない自動インポートの名前 (対象外のファイルやパースできないときは空)
+    fn missing_imports(&self, uri: &Url, text: &str) -> Vec<ImportChange> {
+        let Some(path) = self.target_path(uri) else {
+            return vec![];
+        };
+        transform_source(text, &path, &self.options, self.registry(&path)).added_imports
+    }
+
+    fn code_actions(&self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        let (Some(text), Some(path)) = (self.documents.get(uri), self.target_path(uri)) else {
+            return vec![];
+        };
+        let requested = |kind: &CodeActionKind| {
+            params.context.only.as_ref().is_none_or(|only| {
+                only.iter()
+                    .any(|prefix| kind.as_str().starts_with(prefix.as_str()))
+            })
+        };
+        let registry = self.registry(&path);
+        let missing = self.missing_imports(uri, text);
+        let mut actions = vec![];
+
+        if requested(&CodeActionKind::QUICKFIX) {
+            for change in &missing {
+                let diagnostic = diagnostic(text, change);
+                if !overlaps(diagnostic.range, params.range) {
+                    continue;
+                }
+                let Some(entry) = registry.get(&change.name) else {
+                    continue;
+                };
+                // 名前 1 つだけを持つレジストリで変換して、その import だけを追加する
+                let only = Registry::default().with_entry(entry.clone());
+                if let Some(edit) = self.fix(uri, &path, text, &only) {
+                    actions.push(CodeAction {
+                        title: format!(
+                            "Add `import {{ {} }} from \"{}\"`",
+                            change.name, change.module
+                        ),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic]),
+                        edit: Some(edit),
+                        is_preferred: Some(true),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        // ファイル全体の修正は、カーソル位置の修正に並べるか、`source.fixAll` として明示的に求められたときに出す
+        let mut whole_file = vec![];
+        if missing.len() > 1 && !actions.is_empty() {
+            whole_file.push(CodeActionKind::QUICKFIX);
+        }
+        if !missing.is_empty()
+            && params.context.only.is_some()
+            && requested(&CodeActionKind::SOURCE_FIX_ALL)
+        {
+            whole_file.push(CodeActionKind::SOURCE_FIX_ALL);
+        }
+        for kind in whole_file {
+            if let Some(edit) = self.fix(uri, &path, text, registry) {
+                actions.push(CodeAction {
+                    title: "Add all missing auto-imports".to_string(),
+                    kind: Some(kind),
+                    diagnostics: Some(
+                        missing
+                            .iter()
+                            .map(|change| diagnostic(text, change))
+                            .collect(),
+                    ),
+                    edit: Some(edit),
+                    ..Default::default()
+                });
+            }
+        }
+        actions
+            .into_iter()
+            .map(CodeActionOrCommand::CodeAction)
+            .collect()
+    }
+
+    /// `registry` の名前の import を追加する編集
+    ///
+    /// 通常はスクリプトブロックの先頭に import 文を挿入するだけで、既存の import を
+    /// 書き換えるときだけその範囲をコード生成した内容で置き換える。
+    fn fix(
+        &self,
+        uri: &Url,
+        path: &Path,
+        text: &str,
+        registry: &Registry,
+    ) -> Option<WorkspaceEdit> {
+        let result = transform_source(text, path, &self.options, registry);
+        if !result.is_changed() {
+            return None;
+        }
+        let edits = result
+            .edits
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: Range::new(
+                    offset_position(text, edit.range.start),
+                    offset_position(text, edit.range.end),
+                ),
+                new_text: edit.text,
+            })
+            .collect();
+        Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        })
+    }
+
+    /// 処理の対象になるファイルならそのパス
+    fn target_path(&self, uri: &Url) -> Option<PathBuf> {
+        let path = uri.to_file_path().ok()?;
+        let relative = relative_path(&path, &self.root);
+        (self.filter.has_target_extension(&path) && self.filter.is_included_with_parents(relative))
+            .then_some(path)
+    }
+
+    fn registry(&self, path: &Path) -> &Registry {
+        self.registries.for_path(path, &self.root)
+    }
+}
+
+fn publish_diagnostics(uri: Url, diagnostics: Vec<Diagnostic>) -> Notification {
+    Notification::new(
+        PublishDiagnostics::METHOD.to_string(),
+        PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        },
+    )
+}
+
+/// 足りない import の最初の使用箇所を指す警告
+fn diagnostic(text: &str, change: &ImportChange) -> Diagnostic {
+    let start = position(text, change.line, change.column);
+    let length = change.name.encode_utf16().count() as u32;
+    Diagnostic {
+        range: Range::new(start, Position::new(start.line, start.character + length)),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(MISSING_IMPORT_RULE.to_string())),
+        source: Some(SOURCE.to_string()),
+        message: missing_import_message(change),
+        ..Default::default()
+    }
+}
+
+/// 1 始まりの行と列 (文字単位) を LSP の位置 (0 始まり、列は UTF-16) に直す
+fn position(text: &str, line: usize, column: usize) -> Position {
+    if line == 0 {
+        return Position::new(0, 0);
+    }
+    let source_line = text.lines().nth(line - 1).unwrap_or_default();
+    let character: usize = source_line
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum();
+    Position::new((line - 1) as u32, character as u32)
+}
+
+/// `offset` バイト目の LSP の位置
+fn offset_position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// 範囲が重なるか (端が接しているだけのときも含める)
+fn overlaps(a: Range, b: Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use lsp_types::{CodeActionContext, TextDocumentIdentifier, TextDocumentItem};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn open(server: &mut Server, uri: &Url, text: &str) -> Vec<Diagnostic> {
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "vue".into(), 1, text.into()),
+        };
+        let notification = Notification::new(DidOpenTextDocument::METHOD.to_string(), params);
+        let published = server.handle_notification(notification);
+        let params: PublishDiagnosticsParams =
+            serde_json::from_value(published[0].params.clone()).unwrap();
+        params.diagnostics
+    }
+
+    fn code_actions(server: &Server, uri: &Url, range: Range) -> Vec<CodeAction> {
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range,
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        server
+            .code_actions(&params)
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action,
+                CodeActionOrCommand::Command(_) => panic!("Only code actions are returned"),
+            })
+            .collect()
+    }
+
+    fn edited_text(action: &CodeAction) -> &str {
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        &changes.values().next().unwrap()[0].new_text
+    }
+
+    #[test]
+    fn test_diagnostics_and_code_actions() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let args = Cli::parse_from([
+            "nuxt-auto-import-replacer",
+            "lsp",
+            "-t",
+            root.to_str().unwrap(),
+        ]);
+        let mut server = Server::new(&args).expect("Failed to start the server");
+
+        let uri = Url::from_file_path(root.join("pages/index.vue")).unwrap();
+        let text = "<script setup>\nconst é = useState();\nconst route = useRoute(); // keep me\n</script>\n";
+        let diagnostics = open(&mut server, &uri, text);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 10));
+        assert_eq!(diagnostics[0].range.end, Position::new(1, 18));
+        assert_eq!(diagnostics[0].source.as_deref(), Some("denux"));
+
+        let actions = code_actions(&server, &uri, diagnostics[1].range);
+        assert_eq!(actions.len(), 2, "{:?}", actions);
+        let single = edited_text(&actions[0]);
+        assert!(
+            single.contains("import { useRoute } from \"#imports\";"),
+            "{}",
+            single
+        );
+        assert!(!single.contains("useState }"), "{}", single);
+        let all = edited_text(&actions[1]);
+        assert!(all.contains("useState"), "{}", all);
+        assert!(all.contains("useRoute }"), "{}", all);
+        // コメントを消したり整形し直したりせず、import 文だけを挿入する
+        let changes = actions[1].edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            changes[&uri],
+            [TextEdit {
+                range: Range::new(Position::new(1, 0), Position::new(1, 0)),
+                new_text: "import { useState, useRoute } from \"#imports\";\n".into(),
+            }]
+        );
+
+        let nowhere = Range::new(Position::new(3, 0), Position::new(3, 0));
+        assert!(code_actions(&server, &uri, nowhere).is_empty());
+
+        let excluded = Url::from_file_path(root.join("node_modules/a/index.ts")).unwrap();
+        assert!(open(&mut server, &excluded, "useState();\n").is_empty());
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "const a = 1;\nconst 𝒳 = useState();";
+        assert_eq!(position(text, 2, 11), Position::new(1, 11));
+        assert_eq!(position(text, 0, 0), Position::new(0, 0));
+        assert_eq!(offset_position(text, text.len()), Position::new(1, 22));
+        assert_eq!(offset_position(text, 13), Position::new(1, 0));
+    }
+}
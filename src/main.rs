@@ -1,47 +1,391 @@
 mod cli;
-mod file_processor;
-mod parser;
-mod transformer;
+mod lsp;
+mod watch;
 
 use clap::Parser;
-use cli::Cli;
-use file_processor::{process_ts_file, process_vue_file};
-use parser::process_script_setup;
+use cli::{Cli, Command, Emit};
+use denux::config::Config;
+use denux::file_processor::{
+    is_vue_file, process_script_file, process_source, process_vue_file, FileOutcome, FileResult,
+    SkipReason,
+};
+use denux::filter::{self, relative_path, PathFilter};
+use denux::journal::{self, Journal};
+use denux::parser::{ScriptBlock, ScriptOutput};
+use denux::report::{self, OutputFormat, Totals};
+use denux::{atomic, diff, vcs, Error, NuxtVersion, Options, RegistrySet};
+use ignore::DirEntry;
 use rayon::prelude::*;
-use walkdir::{DirEntry, WalkDir};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
-fn main() {
+/// `--check` で変更が必要なファイルがあったときの終了コード
+const EXIT_CHANGES_NEEDED: u8 = 1;
+/// 読み込みやパースに失敗したファイルがあったときの終了コード
+const EXIT_FAILURE: u8 = 2;
+/// Ctrl-C で中断したときの終了コード (シェルの慣習に合わせて 128 + SIGINT)
+const EXIT_INTERRUPTED: u8 = 130;
+
+/// Ctrl-C が押されたか (処理中のファイルは書き終え、残りのファイルは処理しない)
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn main() -> ExitCode {
     let args = Cli::parse();
+    // `undo` と `--stdin` は Ctrl-C を捕まえず、既定の動作 (SIGINT) ですぐに終了する
+    match args.command {
+        Some(Command::Undo) => return undo(&args),
+        None if args.stdin => return stdin_mode(&args),
+        _ => {}
+    }
+    // 2 回目の Ctrl-C ではすぐに終了する (書き込みは一時ファイル経由なので壊れない)
+    let _ = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED.into());
+        }
+    });
+    match args.command {
+        Some(Command::Watch) => return watch::run(&args),
+        Some(Command::Lsp) => return lsp::run(&args),
+        _ => {}
+    }
+    let started = Instant::now();
 
-    WalkDir::new(&args.target)
-        .into_iter()
+    let loaded = load_filter(&args)
+        .and_then(|filter| check_working_tree(&args, &filter).map(|_| filter))
+        .and_then(|filter| Ok((filter, load_git_paths(&args)?)))
+        .and_then(|loaded| Ok((loaded, load_registries(&args)?)));
+    let ((filter, git_paths), (nuxt_version, registries)) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+
+    let journal = Journal::new(&args.target);
+    let text = args.format == OutputFormat::Text;
+    if args.verbose && text {
+        println!("Using Nuxt {} auto-import registry", nuxt_version);
+    }
+
+    let mut statuses: Vec<(PathBuf, FileResult)> = filter::walk(&args.target, filter.clone())
         .filter_map(Result::ok)
         .par_bridge()
-        .filter(is_target_file)
-        .for_each(|entry| process_entry(entry, &args));
-}
-
-fn is_target_file(entry: &DirEntry) -> bool {
-    matches!(
-        entry.path().extension().and_then(|ext| ext.to_str()),
-        Some("vue") | Some("ts")
-    )
-}
-
-fn process_entry(entry: DirEntry, args: &Cli) {
-    match entry.path().extension().and_then(|ext| ext.to_str()) {
-        Some("vue") => process_vue_file(
-            entry.path(),
-            args.dry_run,
-            args.verbose,
-            process_script_setup,
-        ),
-        Some("ts") => process_ts_file(
-            entry.path(),
-            args.dry_run,
-            args.verbose,
-            process_script_setup,
-        ),
-        _ => (),
+        .filter(|entry| is_target_file(entry, &filter))
+        .filter(|entry| {
+            git_paths
+                .as_ref()
+                .is_none_or(|paths| paths.contains(relative_path(entry.path(), &args.target)))
+        })
+        .map(|entry| {
+            let status = if !filter.is_included(relative_path(entry.path(), &args.target)) {
+                Ok(FileOutcome::Skipped(SkipReason::Excluded))
+            } else if INTERRUPTED.load(Ordering::SeqCst) {
+                Ok(FileOutcome::Skipped(SkipReason::Interrupted))
+            } else {
+                process_entry(entry.path(), &args, &registries, &journal)
+            };
+            if args.format == OutputFormat::Ndjson {
+                println!("{}", report::ndjson_file(entry.path(), &status));
+            }
+            (entry.into_path(), status)
+        })
+        .collect();
+    // 並列処理の順序によらず出力が安定するようパス順に並べる
+    statuses.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut totals = Totals::from_statuses(&statuses);
+    totals.elapsed_ms = started.elapsed().as_millis() as u64;
+    let dry_run = is_dry_run(&args);
+
+    if args.emit == Emit::Patch {
+        if let Err(e) = write_patch(&statuses, &args.patch_file) {
+            eprintln!("Failed to write {:?}: {}", args.patch_file, e);
+            totals.failed += 1;
+        } else if args.verbose && text {
+            println!("Wrote patch to {:?}", args.patch_file);
+        }
+    }
+
+    match args.format {
+        OutputFormat::Text => {
+            report::print_text(&statuses, args.check, args.dry_run, args.verbose);
+            print!("{}", totals.summary());
+            if args.check && totals.updated > 0 {
+                println!("{} file(s) need explicit imports", totals.updated);
+            }
+        }
+        OutputFormat::Json => println!("{}", report::json_report(&statuses, &totals, dry_run)),
+        OutputFormat::Ndjson => println!("{}", report::ndjson_totals(&totals)),
+        OutputFormat::Sarif => println!("{}", report::sarif_report(&statuses)),
+        OutputFormat::Github => {
+            for line in report::github_annotations(&statuses) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        eprintln!("Interrupted: files that were not processed yet are left untouched");
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else if totals.failed > 0 {
+        ExitCode::from(EXIT_FAILURE)
+    } else if args.check && totals.updated > 0 {
+        ExitCode::from(EXIT_CHANGES_NEEDED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// 設定ファイルとコマンドラインの include / exclude から対象ファイルの絞り込みを作る
+fn load_filter(args: &Cli) -> Result<Arc<PathFilter>, Error> {
+    let config = Config::load(args.config.as_deref(), &args.target)?;
+    let include = [config.include, args.include.clone()].concat();
+    let exclude = [config.exclude, args.exclude.clone()].concat();
+    let mut filter = PathFilter::new(&include, &exclude)
+        .expect("include / exclude globs are validated when they are parsed");
+    if let Some(extensions) = args.extensions.clone().or(config.extensions) {
+        filter = filter.with_extensions(extensions);
+    }
+    Ok(Arc::new(filter))
+}
+
+/// ファイルを書き換える前に、対象のファイルにコミットされていない変更がないか確かめる
+///
+/// `cargo fix` と同じく、自分の編集と denux の書き換えが混ざらないようにする。
+/// git リポジトリでなければ確かめない。
+fn check_working_tree(args: &Cli, filter: &PathFilter) -> Result<(), Error> {
+    // `--staged` ではステージされている内容を処理するので、作業ツリーの変更とは混ざらない
+    if args.allow_dirty || args.staged || is_dry_run(args) {
+        return Ok(());
+    }
+    let Some(files) = vcs::dirty_files(&args.target) else {
+        return Ok(());
+    };
+    let dirty: Vec<String> = files
+        .into_iter()
+        .filter(|(path, _)| filter.has_target_extension(path) && filter.is_included(path))
+        .map(|(path, state)| format!("{} ({})", path.display(), state))
+        .collect();
+    if dirty.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::DirtyWorkingTree(dirty))
+    }
+}
+
+/// `--staged` / `--since` で処理するファイル (対象ディレクトリからの相対パス)
+fn load_git_paths(args: &Cli) -> Result<Option<HashSet<PathBuf>>, Error> {
+    let paths = if args.staged {
+        vcs::staged_files(&args.target)?
+    } else if let Some(since) = &args.since {
+        vcs::changed_files(&args.target, since)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(paths.into_iter().collect()))
+}
+
+/// Nuxt のバージョンを決め (指定がなければ package.json から検出)、レジストリを構築する
+fn load_registries(args: &Cli) -> Result<(NuxtVersion, RegistrySet), Error> {
+    let nuxt_version = match args.nuxt_version {
+        Some(version) => version,
+        None => NuxtVersion::detect(&args.target)?.unwrap_or(NuxtVersion::LATEST),
+    };
+    let registries = RegistrySet::load(nuxt_version, &args.target)?;
+    Ok((nuxt_version, registries))
+}
+
+/// `--dry-run` / `--check` / `--emit patch` はファイルを書き換えない
+fn is_dry_run(args: &Cli) -> bool {
+    args.dry_run || args.check || args.emit == Emit::Patch
+}
+
+/// 変更内容を `git apply` で適用できる 1 つのパッチファイルに書き出す
+fn write_patch(statuses: &[(PathBuf, FileResult)], patch_file: &Path) -> io::Result<()> {
+    let patch: String = statuses
+        .iter()
+        .filter_map(|(path, status)| match status {
+            Ok(FileOutcome::Changed {
+                original, updated, ..
+            }) => Some(diff::git_patch(path, original, updated)),
+            _ => None,
+        })
+        .collect();
+
+    atomic::write_file(patch_file, patch.as_bytes())
+}
+
+fn is_target_file(entry: &DirEntry, filter: &PathFilter) -> bool {
+    entry
+        .file_type()
+        .is_some_and(|file_type| file_type.is_file())
+        && filter.has_target_extension(entry.path())
+}
+
+fn process_entry(
+    path: &Path,
+    args: &Cli,
+    registries: &RegistrySet,
+    journal: &Journal,
+) -> FileResult {
+    // 書き込みは元の内容をバックアップしてから journal が行う
+    let options = transform_options(args, &args.target);
+    let processor = script_processor(path, &options, registries);
+    let outcome = if args.staged {
+        let content = vcs::staged_content(&args.target, relative_path(path, &args.target))?;
+        process_source(path, &content, processor)
+    } else if is_vue_file(path) {
+        process_vue_file(path, true, processor)
+    } else {
+        process_script_file(path, true, processor)
+    }?;
+    if let FileOutcome::Changed {
+        original, updated, ..
+    } = &outcome
+    {
+        if !is_dry_run(args) {
+            if args.staged {
+                write_staged(args, path, original, updated, journal)?;
+            } else {
+                journal.write(path, original, updated)?;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// コマンドライン引数から変換の設定を作る (`root` はプロジェクトルート)
+fn transform_options(args: &Cli, root: &Path) -> Options {
+    Options {
+        root: root.to_path_buf(),
+        style: args.import_style,
+        reverse: args.reverse,
+        remove_unused: args.remove_unused,
+    }
+}
+
+/// `path` に合ったレジストリでスクリプトを変換し、使える場所の外で呼ばれたマクロを警告する関数を作る
+fn script_processor<'a>(
+    path: &'a Path,
+    options: &'a Options,
+    registries: &'a RegistrySet,
+) -> impl Fn(&ScriptBlock<'_>) -> Result<ScriptOutput, Error> + 'a {
+    let registry = registries.for_path(path, &options.root);
+    let processor = denux::script_processor(path, options, registry);
+
+    move |block: &ScriptBlock| {
+        let output = processor(block)?;
+        warn_misused_macros(path, &output.misused_macros);
+        warn_virtual_imports(path, &output.virtual_imports);
+        Ok(output)
+    }
+}
+
+/// `--stdin`: 標準入力の内容を `--stdin-filepath` のファイルとして処理し、結果を標準出力に書く
+///
+/// ファイルは書き換えない。変更がなければ入力をそのまま書き、失敗したときは何も書かない。
+fn stdin_mode(args: &Cli) -> ExitCode {
+    let filepath = args
+        .stdin_filepath
+        .as_deref()
+        .expect("--stdin requires --stdin-filepath");
+    // エディタは絶対パスを渡すことが多いので、対象ディレクトリと揃えてから場所を判定する
+    let path = std::path::absolute(filepath).unwrap_or_else(|_| filepath.to_path_buf());
+    let root = std::path::absolute(&args.target).unwrap_or_else(|_| args.target.clone());
+
+    let result = io::read_to_string(io::stdin())
+        .map_err(Error::Read)
+        .and_then(|content| {
+            let (_, registries) = load_registries(args)?;
+            let options = transform_options(args, &root);
+            let processor = script_processor(&path, &options, &registries);
+            let outcome = process_source(&path, &content, processor)?;
+            Ok((content, outcome))
+        });
+
+    match result {
+        Ok((_, FileOutcome::Changed { updated, .. })) => {
+            print!("{}", updated);
+            if args.check {
+                ExitCode::from(EXIT_CHANGES_NEEDED)
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Ok((content, _)) => {
+            print!("{}", content);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprint!("{}", e.diagnostic().render(filepath));
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+/// `--staged`: 結果をステージし直し、作業ツリーがステージされている内容と同じならそれも書き換える
+///
+/// ステージされていない変更があるファイルは、作業ツリーを変えずにステージだけを更新する。
+fn write_staged(
+    args: &Cli,
+    path: &Path,
+    original: &str,
+    updated: &str,
+    journal: &Journal,
+) -> Result<(), Error> {
+    vcs::stage(&args.target, relative_path(path, &args.target), updated)?;
+    let working = fs::read_to_string(path).map_err(Error::Read)?;
+    if working == original {
+        journal.write(path, original, updated)?;
+    } else {
+        eprintln!(
+            "Warning: {:?}: has unstaged changes, only the staged content was updated",
+            path
+        );
+    }
+    Ok(())
+}
+
+/// `undo`: 直前の実行で書き換えたファイルを元に戻す
+fn undo(args: &Cli) -> ExitCode {
+    match journal::undo(&args.target) {
+        Ok(restored) => {
+            for path in &restored {
+                if args.verbose {
+                    println!("Restored {:?}", path);
+                }
+            }
+            println!("Restored {} file(s)", restored.len());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn warn_misused_macros(path: &Path, macros: &[String]) {
+    for name in macros {
+        eprintln!(
+            "Warning: {:?}: compiler macro `{}` is not available in this file",
+            path, name
+        );
+    }
+}
+
+fn warn_virtual_imports(path: &Path, names: &[String]) {
+    for name in names {
+        eprintln!(
+            "Warning: {:?}: `{}` has no public source module, imported from #components instead",
+            path, name
+        );
     }
 }
@@ -1,47 +1,359 @@
-use crate::transformer::ImportInserter;
-use swc_common::{sync::Lrc, FileName, SourceMap};
+use crate::diagnostic::Diagnostic;
+use crate::error::Error;
+use crate::registry::Registry;
+use crate::template::template_references;
+use crate::transformer::{
+    referenced_names, ImportChange, ImportInserter, ImportRemover, TransformOptions,
+    UnusedImportRemover,
+};
+use std::ops::Range;
+use swc_common::{sync::Lrc, BytePos, FileName, SourceMap, Spanned};
 use swc_ecma_ast::*;
 use swc_ecma_codegen::{text_writer::JsWriter, Emitter};
-use swc_ecma_parser::{lexer::Lexer, Parser as SwcParser, StringInput, Syntax};
+use swc_ecma_parser::{lexer::Lexer, EsSyntax, Parser as SwcParser, StringInput, Syntax, TsSyntax};
 use swc_ecma_visit::VisitMutWith;
 
-pub fn process_script_setup(script_content: &str) -> String {
-    let cm: Lrc<SourceMap> = Default::default();
-    let fm = cm.new_source_file(FileName::Anon.into(), script_content.into());
+/// スクリプトの言語 (パーサーの構文を決める)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScriptLang {
+    #[default]
+    Js,
+    Jsx,
+    Ts,
+    Tsx,
+}
 
-    let lexer = Lexer::new(
-        Syntax::Es(Default::default()),
-        EsVersion::Es2022,
-        StringInput::from(&*fm),
-        None,
-    );
-    let mut parser = SwcParser::new_from(lexer);
+impl ScriptLang {
+    /// ファイルの拡張子や `<script lang="...">` の値から言語を決める
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "js" | "mjs" | "cjs" => Some(Self::Js),
+            "jsx" => Some(Self::Jsx),
+            "ts" | "mts" | "cts" => Some(Self::Ts),
+            "tsx" => Some(Self::Tsx),
+            _ => None,
+        }
+    }
 
-    let mut module = match parser.parse_module() {
-        Ok(module) => module,
-        Err(_) => return script_content.to_string(),
-    };
+    /// JSX は `.jsx` / `.tsx` でだけ有効にする (`.ts` の `<T>x` を型アサーションとして読むため)
+    pub fn syntax(self) -> Syntax {
+        match self {
+            Self::Js | Self::Jsx => Syntax::Es(EsSyntax {
+                jsx: self == Self::Jsx,
+                ..Default::default()
+            }),
+            Self::Ts | Self::Tsx => Syntax::Typescript(TsSyntax {
+                tsx: self == Self::Tsx,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// 処理するスクリプトと、同じファイルの周りの情報
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScriptBlock<'a> {
+    pub content: &'a str,
+    pub lang: ScriptLang,
+    /// `<script setup>` か (通常の `<script>` やスクリプトファイルでは false)
+    pub setup: bool,
+    /// 同じ SFC の `<template>` の中身
+    pub template: Option<&'a str>,
+    /// 同じ SFC のほかの `<script>` で import されている名前 (この中には追加しない)
+    pub sibling_imports: &'a [String],
+    /// 同じ SFC のほかの `<script>` で使われている名前 (この中の import は使われているとみなす)
+    pub sibling_references: &'a [String],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptOutput {
+    pub code: String,
+    pub added_imports: Vec<ImportChange>,
+    pub removed_imports: Vec<ImportChange>,
+    /// 使える場所の外で呼ばれたコンパイラマクロ
+    pub misused_macros: Vec<String>,
+    /// `--import-style source` でも仮想モジュールから import した名前
+    pub virtual_imports: Vec<String>,
+}
+
+pub fn process_script_setup(
+    block: &ScriptBlock,
+    registry: &Registry,
+    options: TransformOptions,
+) -> Result<ScriptOutput, Error> {
+    let script_content = block.content;
+    let (cm, mut module) = parse(script_content, block.lang)?;
+
+    let mut output = ScriptOutput::default();
+    let mut rewrote_imports = false;
+    let original_imports: Vec<ImportDecl> = module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => Some(import.clone()),
+            _ => None,
+        })
+        .collect();
+
+    if options.reverse {
+        let mut visitor = ImportRemover::new(registry, options);
+        module.visit_mut_with(&mut visitor);
+        output.removed_imports = visitor.removed_imports;
+    } else {
+        let mut visitor = ImportInserter::new(registry, options);
+        visitor.sibling_imports = block.sibling_imports;
+        module.visit_mut_with(&mut visitor);
+        output.added_imports = visitor.added_imports;
+        output.misused_macros = visitor.misused_macros;
+        output.virtual_imports = visitor.virtual_imports;
+        rewrote_imports = visitor.rewrote_imports;
+    }
+
+    if options.remove_unused {
+        let mut references = block.template.map(template_references).unwrap_or_default();
+        references.extend(block.sibling_references.iter().cloned());
+        let mut visitor = UnusedImportRemover::new(registry, references);
+        module.visit_mut_with(&mut visitor);
+        output.removed_imports.extend(visitor.removed_imports);
+    }
+
+    for change in output
+        .added_imports
+        .iter_mut()
+        .chain(output.removed_imports.iter_mut())
+    {
+        locate(&cm, change);
+    }
+
+    // import に変更がなければ、コード生成による整形の差分を出さないよう元の内容を返す
+    if output.added_imports.is_empty() && output.removed_imports.is_empty() && !rewrote_imports {
+        output.code = script_content.to_string();
+        return Ok(output);
+    }
+
+    // コメントや整形を残すよう、追加した import 文の挿入と取り除いた名前の切り取りだけを元の内容に適用する
+    // (仮想モジュールからの import を書き換えたときだけ、コード生成した内容で置き換える)
+    if !rewrote_imports {
+        let offset = |pos: BytePos| cm.lookup_byte_offset(pos).pos.0 as usize;
+        let mut edits: Vec<(Range<usize>, String)> = original_imports
+            .iter()
+            .flat_map(|import| {
+                removal_ranges(script_content, import, &output.removed_imports, offset)
+            })
+            .map(|range| (range, String::new()))
+            .collect();
+
+        // 追加した import 文は先頭にあり、パースした文と違って位置を持たない
+        let inserted = module
+            .body
+            .iter()
+            .take_while(|item| item.span().is_dummy())
+            .count();
+        if inserted > 0 {
+            module.body.truncate(inserted);
+            let newline = if script_content.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            };
+            let shebang = module.shebang.take().is_some();
+            let at = insertion_offset(script_content, shebang);
+            edits.push((at..at, emit(cm, &module, newline)?));
+        }
+
+        // 同じ位置では挿入を先に適用する
+        edits.sort_by_key(|(range, _)| (range.start, range.end));
+        let mut code = String::new();
+        let mut cursor = 0;
+        for (range, text) in edits {
+            code.push_str(&script_content[cursor..range.start]);
+            code.push_str(&text);
+            cursor = range.end;
+        }
+        code.push_str(&script_content[cursor..]);
+        output.code = code;
+        return Ok(output);
+    }
+
+    output.code = emit(cm, &module, "\n")?;
+    Ok(output)
+}
+
+/// `import` 宣言から取り除いた名前を元の内容から切り取る範囲 (バイト範囲)
+///
+/// 名前がすべて取り除かれたら宣言ごと、ほかに何もない行なら行ごと切り取る。
+fn removal_ranges(
+    script_content: &str,
+    import: &ImportDecl,
+    removed: &[ImportChange],
+    offset: impl Fn(BytePos) -> usize,
+) -> Vec<Range<usize>> {
+    let is_removed: Vec<bool> = import
+        .specifiers
+        .iter()
+        .map(|specifier| removed.iter().any(|change| change.span == specifier.span()))
+        .collect();
+    if !is_removed.contains(&true) {
+        return vec![];
+    }
 
-    let mut visitor = ImportInserter::new();
-    module.visit_mut_with(&mut visitor);
+    if !is_removed.contains(&false) {
+        let (mut start, mut end) = (offset(import.span.lo), offset(import.span.hi));
+        let line_start = script_content[..start]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let rest = &script_content[end..];
+        let trailing = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let line_end = if rest[trailing..].starts_with("\r\n") {
+            Some(end + trailing + 2)
+        } else if rest[trailing..].starts_with('\n') || trailing == rest.len() {
+            Some((end + trailing + 1).min(script_content.len()))
+        } else {
+            None
+        };
+        if let (true, Some(line_end)) = (
+            script_content[line_start..start].trim().is_empty(),
+            line_end,
+        ) {
+            (start, end) = (line_start, line_end);
+        } else {
+            // 同じ行に続くコメントなどの前の空白も切り取る
+            end += trailing;
+        }
+        return std::iter::once(start..end).collect();
+    }
 
+    let spans: Vec<(usize, usize)> = import
+        .specifiers
+        .iter()
+        .map(|specifier| (offset(specifier.span().lo), offset(specifier.span().hi)))
+        .collect();
+    let named = |index: usize| matches!(import.specifiers[index], ImportSpecifier::Named(_));
+    let mut ranges = vec![];
+    let mut index = 0;
+    while index < spans.len() {
+        if !is_removed[index] {
+            index += 1;
+            continue;
+        }
+        // 続けて取り除いた名前をまとめ、区切りのカンマも切り取る
+        let first = index;
+        while index < spans.len() && is_removed[index] {
+            index += 1;
+        }
+        let last = index - 1;
+        if index < spans.len() {
+            ranges.push(spans[first].0..spans[index].0);
+        } else if named(first - 1) {
+            ranges.push(spans[first - 1].1..spans[last].1);
+        } else {
+            // `import Foo, { a } from` の `{ a }` がすべてなくなったら括弧ごと切り取る
+            let close = script_content[spans[last].1..]
+                .find('}')
+                .map_or(spans[last].1, |index| spans[last].1 + index + 1);
+            ranges.push(spans[first - 1].1..close);
+        }
+    }
+    ranges
+}
+
+fn emit(cm: Lrc<SourceMap>, module: &Module, newline: &str) -> Result<String, Error> {
     let mut buf = vec![];
     {
         let mut emitter = Emitter {
             cfg: Default::default(),
             cm: cm.clone(),
             comments: None,
-            wr: JsWriter::new(cm, "\n", &mut buf, None),
+            wr: JsWriter::new(cm, newline, &mut buf, None),
         };
-        emitter.emit_module(&module).unwrap();
+        emitter
+            .emit_module(module)
+            .map_err(|e| Error::Codegen(e.to_string()))?;
+    }
+    String::from_utf8(buf).map_err(|e| Error::Codegen(e.to_string()))
+}
+
+/// import 文を挿入する位置 (先頭の空行と shebang の行の後ろ)
+fn insertion_offset(script_content: &str, shebang: bool) -> usize {
+    let start = if shebang {
+        script_content
+            .find('\n')
+            .map_or(script_content.len(), |end| end + 1)
+    } else {
+        0
+    };
+    let rest = &script_content[start..];
+    start + rest.len() - rest.trim_start_matches(['\r', '\n']).len()
+}
+
+/// スクリプトの import 宣言以外で使われている名前 (パースできなければ空)
+pub fn script_references(script_content: &str, lang: ScriptLang) -> Vec<String> {
+    let Ok((_, module)) = parse(script_content, lang) else {
+        return vec![];
+    };
+    referenced_names(&module.body).into_iter().collect()
+}
+
+/// スクリプトで import されているローカルな名前 (パースできなければ空)
+pub fn imported_names(script_content: &str, lang: ScriptLang) -> Vec<String> {
+    let Ok((_, module)) = parse(script_content, lang) else {
+        return vec![];
+    };
+    module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => Some(&import.specifiers),
+            _ => None,
+        })
+        .flatten()
+        .map(|specifier| match specifier {
+            ImportSpecifier::Named(named) => named.local.sym.to_string(),
+            ImportSpecifier::Default(default) => default.local.sym.to_string(),
+            ImportSpecifier::Namespace(namespace) => namespace.local.sym.to_string(),
+        })
+        .collect()
+}
+
+fn parse(script_content: &str, lang: ScriptLang) -> Result<(Lrc<SourceMap>, Module), Error> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon.into(), script_content.to_string());
+
+    let lexer = Lexer::new(
+        lang.syntax(),
+        EsVersion::Es2022,
+        StringInput::from(&*fm),
+        None,
+    );
+    let mut parser = SwcParser::new_from(lexer);
+
+    // パースエラーの位置はスクリプト内の行と列
+    let parse_error = |err: swc_ecma_parser::error::Error| {
+        let loc = cm.lookup_char_pos(err.span().lo);
+        Error::Parse(Diagnostic::new(err.kind().msg(), loc.line, loc.col.0 + 1))
+    };
+    let module = parser.parse_module().map_err(parse_error)?;
+    // 回復できたエラー (別の言語の構文など) も書き換えずにエラーとして扱う
+    if let Some(err) = parser.take_errors().into_iter().next() {
+        return Err(parse_error(err));
     }
+    Ok((cm, module))
+}
 
-    String::from_utf8(buf).unwrap()
+/// `span` から 1 始まりの行と列を求める
+fn locate(cm: &SourceMap, change: &mut ImportChange) {
+    if change.span.is_dummy() {
+        return;
+    }
+    let loc = cm.lookup_char_pos(change.span.lo);
+    change.line = loc.line;
+    change.column = loc.col.0 + 1;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::registry::{Context, NuxtVersion};
     use std::cell::RefCell;
     use swc_ecma_visit::VisitMut;
 
@@ -90,15 +402,258 @@ mod tests {
         );
     }
 
-    /// Test if `parser.parse_module()` fails, the original script content is returned
+    /// Test if `parser.parse_module()` fails, a parse error with its location is returned
     #[test]
     fn test_process_script_setup_parser_fails() {
         let script_content = "const a ="; // Incomplete code to trigger a syntax error
-        let result = process_script_setup(script_content);
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Js,
+                ..Default::default()
+            },
+            &Registry::default(),
+            TransformOptions::default(),
+        );
+
+        let Err(Error::Parse(error)) = result else {
+            panic!("The parse error should be reported");
+        };
+        assert_eq!((error.line, error.column), (1, 10));
+    }
+
+    /// Test if added imports are inserted without reformatting the script or dropping comments
+    #[test]
+    fn test_process_script_setup_only_inserts_imports() {
+        let script_content = "\n// fetch the current route\nconst route = useRoute() // here\n\nconst  count =  ref(0)\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Ts,
+                setup: true,
+                ..Default::default()
+            },
+            &registry,
+            TransformOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.code,
+            format!(
+                "\nimport {{ useRoute, ref }} from \"#imports\";\n{}",
+                &script_content[1..]
+            )
+        );
+
+        let script_content = "#!/usr/bin/env node\r\nconst count = ref(0)\r\n";
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Js,
+                ..Default::default()
+            },
+            &registry,
+            TransformOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.code,
+            "#!/usr/bin/env node\r\nimport { ref } from \"#imports\";\r\nconst count = ref(0)\r\n"
+        );
+    }
+
+    /// Test if `--reverse` only cuts the removed names out of the script and keeps comments
+    #[test]
+    fn test_process_script_setup_reverse_keeps_comments() {
+        let script_content = "\r\n// important comment\r\nimport { ref, computed } from 'vue' // trailing\r\nimport { watch, h as hyper } from \"vue\";\r\nimport {\r\n  useRoute,\r\n} from '#imports'\r\n/* block */\r\nconst count = ref(0)\r\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Ts,
+                setup: true,
+                ..Default::default()
+            },
+            &registry,
+            options,
+        )
+        .unwrap();
+
+        assert_eq!(result.removed_imports.len(), 4);
+        assert_eq!(
+            result.code,
+            "\r\n// important comment\r\n// trailing\r\nimport { h as hyper } from \"vue\";\r\n/* block */\r\nconst count = ref(0)\r\n"
+        );
+    }
+
+    /// Test if `--remove-unused` only cuts the unused names out of the script and keeps comments
+    #[test]
+    fn test_process_script_setup_remove_unused_keeps_comments() {
+        let script_content = "\n// important comment\nimport { computed, ref } from 'vue' // trailing\n/* block */\nconst count = ref(0)\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            remove_unused: true,
+            ..Default::default()
+        };
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Ts,
+                setup: true,
+                ..Default::default()
+            },
+            &registry,
+            options,
+        )
+        .unwrap();
 
         assert_eq!(
-            result, script_content,
-            "If the parser fails, the original script content should be returned"
+            result.code,
+            "\n// important comment\nimport { ref } from 'vue' // trailing\n/* block */\nconst count = ref(0)\n"
         );
     }
+
+    /// Test if unused auto-imports are removed unless the template references them
+    #[test]
+    fn test_process_script_setup_removes_unused_imports() {
+        let script_content = "import { ref, computed } from 'vue';\nimport { NuxtLink } from '#components';\nconst a = ref(1);\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            remove_unused: true,
+            ..Default::default()
+        };
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Js,
+                template: Some("<nuxt-link to=\"/\">Home</nuxt-link>"),
+                ..Default::default()
+            },
+            &registry,
+            options,
+        )
+        .unwrap();
+
+        assert!(result.code.contains("import { ref } from 'vue';"));
+        assert!(result
+            .code
+            .contains("import { NuxtLink } from '#components';"));
+        assert!(!result.code.contains("computed"));
+    }
+
+    /// Test if a script without import changes is returned untouched instead of being reformatted
+    #[test]
+    fn test_process_script_setup_keeps_unchanged_script() {
+        let script_content = "const   a = 42; // answer\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Js,
+                ..Default::default()
+            },
+            &registry,
+            TransformOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.code, script_content);
+        assert!(result.added_imports.is_empty());
+    }
+
+    /// Test if added imports point at the first usage of the name
+    #[test]
+    fn test_process_script_setup_locates_first_usage() {
+        let script_content = "const a = 1;\nconst b = useRoute();\nuseRoute();\n";
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: script_content,
+                lang: ScriptLang::Js,
+                ..Default::default()
+            },
+            &registry,
+            TransformOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.added_imports.len(), 1);
+        let added = &result.added_imports[0];
+        assert_eq!(added.name, "useRoute");
+        assert_eq!(added.module, "#imports");
+        assert_eq!((added.line, added.column), (2, 11));
+    }
+
+    /// Test if each language is parsed with its own syntax
+    #[test]
+    fn test_process_script_setup_uses_syntax_for_lang() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let process = |source: &str, lang| {
+            process_script_setup(
+                &ScriptBlock {
+                    content: source,
+                    lang,
+                    ..Default::default()
+                },
+                &registry,
+                TransformOptions::default(),
+            )
+        };
+
+        let ts = "const count: Ref<number> = useState<number>('count', () => <number>0);\n";
+        let result = process(ts, ScriptLang::Ts).expect("TypeScript should be parsed");
+        assert!(result.code.contains("useState<number>"));
+        assert!(process(ts, ScriptLang::Js).is_err());
+
+        let tsx = "const el = <NuxtLink to=\"/\">{useRoute().path}</NuxtLink>;\n";
+        let result = process(tsx, ScriptLang::Tsx).expect("TSX should be parsed");
+        assert!(result
+            .added_imports
+            .iter()
+            .any(|change| change.name == "useRoute"));
+        assert!(process(tsx, ScriptLang::Ts).is_err());
+        assert!(process(tsx, ScriptLang::Jsx).is_ok());
+    }
+
+    /// Test if names imported in the other `<script>` block are not imported again
+    #[test]
+    fn test_process_script_setup_skips_sibling_imports() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let sibling = "import { ref } from 'vue';\nimport * as api from '~/api';\n";
+        let sibling_imports = imported_names(sibling, ScriptLang::Ts);
+        assert_eq!(sibling_imports, vec!["ref", "api"]);
+
+        let result = process_script_setup(
+            &ScriptBlock {
+                content: "const count = ref(0);\nconst route = useRoute();\n",
+                setup: true,
+                sibling_imports: &sibling_imports,
+                ..Default::default()
+            },
+            &registry,
+            TransformOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(names(&result.added_imports), vec!["useRoute"]);
+        assert!(!result.code.contains("ref }"));
+    }
+
+    fn names(changes: &[ImportChange]) -> Vec<&str> {
+        changes.iter().map(|change| change.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_script_lang_from_extension() {
+        assert_eq!(ScriptLang::from_extension("mjs"), Some(ScriptLang::Js));
+        assert_eq!(ScriptLang::from_extension("cts"), Some(ScriptLang::Ts));
+        assert_eq!(ScriptLang::from_extension("tsx"), Some(ScriptLang::Tsx));
+        assert_eq!(ScriptLang::from_extension("vue"), None);
+    }
 }
@@ -0,0 +1,891 @@
+use crate::error::Error;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+/// Nuxt の major.minor バージョン
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NuxtVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl NuxtVersion {
+    /// 組み込みレジストリが把握している最新バージョン
+    pub const LATEST: NuxtVersion = NuxtVersion::new(4, 0);
+
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// `package.json` の `dependencies` / `devDependencies` から Nuxt のバージョンを読み取る
+    ///
+    /// ファイルがない、または Nuxt に依存していなければ `None`。壊れた JSON はエラーにする。
+    pub fn from_package_json(path: &Path) -> Result<Option<Self>, Error> {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Ok(None);
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&content).map_err(|e| Error::Config {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            })?;
+
+        Ok(["dependencies", "devDependencies"]
+            .iter()
+            .filter_map(|section| json.get(section)?.get("nuxt")?.as_str())
+            .find_map(|spec| spec.parse().ok()))
+    }
+
+    /// `dir` から親ディレクトリへ遡り、最初に Nuxt を依存に持つ `package.json` を探す
+    pub fn detect(dir: &Path) -> Result<Option<Self>, Error> {
+        let Ok(dir) = dir.canonicalize() else {
+            return Ok(None);
+        };
+        for ancestor in dir.ancestors() {
+            if let Some(version) = Self::from_package_json(&ancestor.join("package.json"))? {
+                return Ok(Some(version));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FromStr for NuxtVersion {
+    type Err = String;
+
+    /// `3`, `3.12`, `3.12.4`, `^3.12.4`, `~3.10.0` のような表記を受け付ける
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim().trim_start_matches(|c: char| !c.is_ascii_digit());
+        let mut parts = trimmed.split(|c: char| !c.is_ascii_digit());
+
+        let major = parts
+            .next()
+            .and_then(|part| part.parse().ok())
+            .ok_or_else(|| format!("invalid Nuxt version: {:?}", s))?;
+        let minor = parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+
+        Ok(Self::new(major, minor))
+    }
+}
+
+impl fmt::Display for NuxtVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// ファイルが属する実行環境
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// Vue アプリ側 (`pages/`, `components/`, `composables/` など)
+    App,
+    /// Nitro サーバー側 (`server/` 以下)
+    Server,
+}
+
+impl Context {
+    /// `root` からの相対パスが `server/` で始まるファイルをサーバー側として扱う
+    pub fn from_path(path: &Path, root: &Path) -> Self {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        match relative.components().next() {
+            Some(Component::Normal(first)) if first == "server" => Context::Server,
+            _ => Context::App,
+        }
+    }
+}
+
+/// `root` からの相対パスが `pages/` (Nuxt 4 では `app/pages/`) 以下のファイルをページとして扱う
+pub fn is_page_path(path: &Path, root: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut components = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        });
+
+    match components.next() {
+        Some("pages") => true,
+        Some("app") => components.next() == Some("pages"),
+        _ => false,
+    }
+}
+
+/// 自動インポートされる名前の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    /// コンポーザブルやユーティリティ関数
+    Function,
+    /// テンプレートや JSX で使われるコンポーネント
+    Component,
+    /// コンパイラマクロ (インポートしてはいけない)
+    Macro(MacroScope),
+}
+
+/// コンパイラマクロが使える場所
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroScope {
+    /// 任意の `<script setup>` (Vue のマクロ)
+    ScriptSetup,
+    /// ページコンポーネントの `<script setup>` (Nuxt のマクロ)
+    PageScriptSetup,
+}
+
+/// 処理中のスクリプトがどこに書かれているか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptScope {
+    /// `<script setup>` の中かどうか
+    pub setup: bool,
+    /// ページコンポーネントかどうか
+    pub page: bool,
+}
+
+impl ScriptScope {
+    pub fn allows(&self, scope: MacroScope) -> bool {
+        match scope {
+            MacroScope::ScriptSetup => self.setup,
+            MacroScope::PageScriptSetup => self.setup && self.page,
+        }
+    }
+}
+
+/// 追加する import 文のインポート元の書き方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportStyle {
+    /// Nuxt の仮想モジュール (`#imports`, `#components`)
+    #[default]
+    Virtual,
+    /// 実際のモジュール (`vue`, `vue-router`, `nuxt/app`, `~/composables/useCart` など)
+    Source,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryEntry {
+    pub name: String,
+    /// Nuxt の仮想モジュール (`#imports` / `#components`)
+    pub module: String,
+    /// 名前を実際に export しているモジュール
+    pub source: String,
+    pub kind: ImportKind,
+}
+
+impl RegistryEntry {
+    pub fn module_for(&self, style: ImportStyle) -> &str {
+        match style {
+            ImportStyle::Virtual => &self.module,
+            ImportStyle::Source => &self.source,
+        }
+    }
+}
+
+/// 組み込みテーブルの 1 行: (名前, 追加された Nuxt の (major, minor))
+type Builtin = (&'static str, (u32, u32));
+
+/// Nuxt コアのコンポーザブルとユーティリティ (`#imports`)
+const NUXT_FUNCTIONS: &[Builtin] = &[
+    ("abortNavigation", (3, 0)),
+    ("addRouteMiddleware", (3, 0)),
+    ("callOnce", (3, 9)),
+    ("clearError", (3, 0)),
+    ("clearNuxtData", (3, 0)),
+    ("clearNuxtState", (3, 6)),
+    ("createError", (3, 0)),
+    ("defineAppConfig", (3, 0)),
+    ("defineNuxtComponent", (3, 0)),
+    ("defineNuxtLink", (3, 0)),
+    ("defineNuxtPlugin", (3, 0)),
+    ("defineNuxtRouteMiddleware", (3, 0)),
+    ("definePayloadPlugin", (3, 4)),
+    ("definePayloadReducer", (3, 4)),
+    ("definePayloadReviver", (3, 4)),
+    ("isNuxtError", (3, 0)),
+    ("navigateTo", (3, 0)),
+    ("onNuxtReady", (3, 3)),
+    ("onPrehydrate", (3, 12)),
+    ("prefetchComponents", (3, 0)),
+    ("preloadComponents", (3, 0)),
+    ("preloadRouteComponents", (3, 2)),
+    ("prerenderRoutes", (3, 7)),
+    ("refreshCookie", (3, 10)),
+    ("refreshNuxtData", (3, 0)),
+    ("reloadNuxtApp", (3, 3)),
+    ("setPageLayout", (3, 0)),
+    ("setResponseStatus", (3, 0)),
+    ("showError", (3, 0)),
+    ("updateAppConfig", (3, 0)),
+    ("useAppConfig", (3, 0)),
+    ("useAsyncData", (3, 0)),
+    ("useCookie", (3, 0)),
+    ("useError", (3, 0)),
+    ("useFetch", (3, 0)),
+    ("useHead", (3, 0)),
+    ("useHeadSafe", (3, 3)),
+    ("useHydration", (3, 0)),
+    ("useId", (3, 10)),
+    ("useLazyAsyncData", (3, 0)),
+    ("useLazyFetch", (3, 0)),
+    ("useLoadingIndicator", (3, 9)),
+    ("useNuxtApp", (3, 0)),
+    ("useNuxtData", (3, 1)),
+    ("usePreviewMode", (3, 11)),
+    ("useRequestEvent", (3, 0)),
+    ("useRequestFetch", (3, 2)),
+    ("useRequestHeader", (3, 9)),
+    ("useRequestHeaders", (3, 0)),
+    ("useRequestURL", (3, 5)),
+    ("useResponseHeader", (3, 14)),
+    ("useRoute", (3, 0)),
+    ("useRouteAnnouncer", (3, 12)),
+    ("useRouter", (3, 0)),
+    ("useRuntimeConfig", (3, 0)),
+    ("useRuntimeHook", (3, 14)),
+    ("useSeoMeta", (3, 1)),
+    ("useServerHead", (3, 1)),
+    ("useServerHeadSafe", (3, 3)),
+    ("useServerSeoMeta", (3, 1)),
+    ("useState", (3, 0)),
+];
+
+/// Nuxt が自動インポートする Vue Router の API (`#imports`)
+const VUE_ROUTER_FUNCTIONS: &[Builtin] = &[
+    ("onBeforeRouteLeave", (3, 0)),
+    ("onBeforeRouteUpdate", (3, 0)),
+];
+
+/// Nuxt が自動インポートする Vue の API (`#imports`)
+const VUE_FUNCTIONS: &[Builtin] = &[
+    ("computed", (3, 0)),
+    ("customRef", (3, 0)),
+    ("defineAsyncComponent", (3, 0)),
+    ("defineComponent", (3, 0)),
+    ("effectScope", (3, 0)),
+    ("getCurrentInstance", (3, 0)),
+    ("getCurrentScope", (3, 0)),
+    ("h", (3, 0)),
+    ("inject", (3, 0)),
+    ("isProxy", (3, 0)),
+    ("isReactive", (3, 0)),
+    ("isReadonly", (3, 0)),
+    ("isRef", (3, 0)),
+    ("isShallow", (3, 0)),
+    ("markRaw", (3, 0)),
+    ("nextTick", (3, 0)),
+    ("onActivated", (3, 0)),
+    ("onBeforeMount", (3, 0)),
+    ("onBeforeUnmount", (3, 0)),
+    ("onBeforeUpdate", (3, 0)),
+    ("onDeactivated", (3, 0)),
+    ("onErrorCaptured", (3, 0)),
+    ("onMounted", (3, 0)),
+    ("onRenderTracked", (3, 0)),
+    ("onRenderTriggered", (3, 0)),
+    ("onScopeDispose", (3, 0)),
+    ("onServerPrefetch", (3, 0)),
+    ("onUnmounted", (3, 0)),
+    ("onUpdated", (3, 0)),
+    ("onWatcherCleanup", (3, 13)),
+    ("provide", (3, 0)),
+    ("reactive", (3, 0)),
+    ("readonly", (3, 0)),
+    ("ref", (3, 0)),
+    ("resolveComponent", (3, 0)),
+    ("shallowReactive", (3, 0)),
+    ("shallowReadonly", (3, 0)),
+    ("shallowRef", (3, 0)),
+    ("toRaw", (3, 0)),
+    ("toRef", (3, 0)),
+    ("toRefs", (3, 0)),
+    ("toValue", (3, 5)),
+    ("triggerRef", (3, 0)),
+    ("unref", (3, 0)),
+    ("useAttrs", (3, 0)),
+    ("useCssModule", (3, 0)),
+    ("useCssVars", (3, 0)),
+    ("useModel", (3, 10)),
+    ("useSlots", (3, 0)),
+    ("useTemplateRef", (3, 13)),
+    ("watch", (3, 0)),
+    ("watchEffect", (3, 0)),
+    ("watchPostEffect", (3, 0)),
+    ("watchSyncEffect", (3, 0)),
+];
+
+/// Vue のコンパイラマクロ
+const VUE_MACROS: &[Builtin] = &[
+    ("defineEmits", (3, 0)),
+    ("defineExpose", (3, 0)),
+    ("defineModel", (3, 10)),
+    ("defineOptions", (3, 8)),
+    ("defineProps", (3, 0)),
+    ("defineSlots", (3, 8)),
+    ("withDefaults", (3, 0)),
+];
+
+/// ページでのみ使える Nuxt のコンパイラマクロ
+const NUXT_PAGE_MACROS: &[Builtin] = &[("definePageMeta", (3, 0)), ("defineRouteRules", (3, 10))];
+
+/// Nuxt の組み込みコンポーネント (`#components`)
+const NUXT_COMPONENTS: &[Builtin] = &[
+    ("ClientOnly", (3, 0)),
+    ("DevOnly", (3, 3)),
+    ("NuxtClientFallback", (3, 4)),
+    ("NuxtErrorBoundary", (3, 0)),
+    ("NuxtIsland", (3, 0)),
+    ("NuxtLayout", (3, 0)),
+    ("NuxtLink", (3, 0)),
+    ("NuxtLoadingIndicator", (3, 0)),
+    ("NuxtPage", (3, 0)),
+    ("NuxtRouteAnnouncer", (3, 12)),
+    ("NuxtWelcome", (3, 0)),
+    ("ServerPlaceholder", (3, 0)),
+];
+
+/// Nuxt がコンポーネントとして扱う Vue の組み込みコンポーネント (`#components` にはないので `vue` から)
+const VUE_COMPONENTS: &[Builtin] = &[("Suspense", (3, 0))];
+
+/// h3 のイベントハンドラとユーティリティ (Nitro コンテキストの `#imports`)
+const H3_FUNCTIONS: &[Builtin] = &[
+    ("appendResponseHeader", (3, 0)),
+    ("createError", (3, 0)),
+    ("defineEventHandler", (3, 0)),
+    ("defineWebSocketHandler", (3, 11)),
+    ("deleteCookie", (3, 0)),
+    ("eventHandler", (3, 0)),
+    ("getCookie", (3, 0)),
+    ("getHeader", (3, 0)),
+    ("getHeaders", (3, 0)),
+    ("getMethod", (3, 0)),
+    ("getQuery", (3, 0)),
+    ("getRequestHeader", (3, 0)),
+    ("getRequestHeaders", (3, 0)),
+    ("getRequestIP", (3, 0)),
+    ("getRequestURL", (3, 0)),
+    ("getRouterParam", (3, 0)),
+    ("getRouterParams", (3, 0)),
+    ("getValidatedQuery", (3, 7)),
+    ("getValidatedRouterParams", (3, 7)),
+    ("parseCookies", (3, 0)),
+    ("proxyRequest", (3, 0)),
+    ("readBody", (3, 0)),
+    ("readMultipartFormData", (3, 0)),
+    ("readRawBody", (3, 0)),
+    ("readValidatedBody", (3, 7)),
+    ("sendError", (3, 0)),
+    ("sendNoContent", (3, 0)),
+    ("sendRedirect", (3, 0)),
+    ("sendStream", (3, 0)),
+    ("setCookie", (3, 0)),
+    ("setHeader", (3, 0)),
+    ("setHeaders", (3, 0)),
+    ("setResponseHeader", (3, 0)),
+    ("setResponseHeaders", (3, 0)),
+    ("setResponseStatus", (3, 0)),
+];
+
+/// Nitro のランタイム API (Nitro コンテキストの `#imports`)
+const NITRO_FUNCTIONS: &[Builtin] = &[
+    ("cachedEventHandler", (3, 0)),
+    ("cachedFunction", (3, 0)),
+    ("defineCachedEventHandler", (3, 0)),
+    ("defineCachedFunction", (3, 0)),
+    ("defineNitroErrorHandler", (3, 14)),
+    ("defineNitroPlugin", (3, 0)),
+    ("defineTask", (3, 11)),
+    ("runTask", (3, 11)),
+    ("useAppConfig", (3, 0)),
+    ("useNitroApp", (3, 0)),
+    ("useRuntimeConfig", (3, 0)),
+    ("useStorage", (3, 0)),
+];
+
+/// 名前から自動インポート元を引くためのレジストリ
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    entries: HashMap<String, RegistryEntry>,
+}
+
+impl Registry {
+    /// 指定した Nuxt バージョンとコンテキストで利用できる組み込みの自動インポートを持つレジストリを作る
+    pub fn builtin(version: NuxtVersion, context: Context) -> Self {
+        let mut registry = Self::default();
+
+        let tables = match context {
+            Context::App => vec![
+                (NUXT_FUNCTIONS, "#imports", "nuxt/app", ImportKind::Function),
+                (
+                    VUE_ROUTER_FUNCTIONS,
+                    "#imports",
+                    "vue-router",
+                    ImportKind::Function,
+                ),
+                (VUE_FUNCTIONS, "#imports", "vue", ImportKind::Function),
+                (
+                    NUXT_COMPONENTS,
+                    "#components",
+                    "#components",
+                    ImportKind::Component,
+                ),
+                (VUE_COMPONENTS, "vue", "vue", ImportKind::Component),
+                (
+                    VUE_MACROS,
+                    "vue",
+                    "vue",
+                    ImportKind::Macro(MacroScope::ScriptSetup),
+                ),
+                (
+                    NUXT_PAGE_MACROS,
+                    "#imports",
+                    "nuxt/app",
+                    ImportKind::Macro(MacroScope::PageScriptSetup),
+                ),
+            ],
+            Context::Server => vec![
+                (H3_FUNCTIONS, "#imports", "h3", ImportKind::Function),
+                (
+                    NITRO_FUNCTIONS,
+                    "#imports",
+                    "nitropack/runtime",
+                    ImportKind::Function,
+                ),
+            ],
+        };
+        for (table, module, source, kind) in tables {
+            for &(name, (major, minor)) in table {
+                if NuxtVersion::new(major, minor) <= version {
+                    registry.insert(RegistryEntry {
+                        name: name.into(),
+                        module: module.into(),
+                        source: source.into(),
+                        kind,
+                    });
+                }
+            }
+        }
+
+        registry
+    }
+
+    /// `dir` 以下のファイルから名前付き export を読み取り、`#imports` の自動インポートとして登録する
+    ///
+    /// 実際のインポート元は `alias` にファイルの相対パス (拡張子なし) をつなげたものになる。
+    /// 例えば `alias` が `~/composables` なら `composables/useCart.ts` は `~/composables/useCart`。
+    /// Nitro の `server/utils/` と同じくサブディレクトリも読む。
+    ///
+    /// `dir` がなければ何もしない。読めないファイルがあればエラーにする。
+    pub fn scan_exports(&mut self, dir: &Path, alias: &str) -> Result<(), Error> {
+        self.register_exports(dir, alias, false)
+    }
+
+    /// Nuxt の `composables/` と `utils/` と同じく、`dir` 直下のファイルと
+    /// `<dir>/*/index.*` だけから export を登録する (ほかは `scan_exports` と同じ)
+    pub fn scan_top_level_exports(&mut self, dir: &Path, alias: &str) -> Result<(), Error> {
+        self.register_exports(dir, alias, true)
+    }
+
+    fn register_exports(&mut self, dir: &Path, alias: &str, top_level: bool) -> Result<(), Error> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let export_re = Regex::new(
+            r"(?m)^\s*export\s+(?:async\s+)?(?:function\*?|const|let|var|class)\s+([A-Za-z_$][\w$]*)",
+        )
+        .unwrap();
+
+        let walk = if top_level {
+            WalkDir::new(dir).max_depth(2)
+        } else {
+            WalkDir::new(dir)
+        };
+        let files = walk.into_iter().filter_map(Result::ok).filter(|entry| {
+            matches!(
+                entry.path().extension().and_then(|ext| ext.to_str()),
+                Some("ts") | Some("js") | Some("mjs") | Some("mts")
+            ) && (!top_level
+                || entry.depth() == 1
+                || entry.path().file_stem().is_some_and(|stem| stem == "index"))
+        });
+
+        for file in files {
+            let content = fs::read_to_string(file.path()).map_err(|source| Error::Registry {
+                path: file.path().to_path_buf(),
+                source,
+            })?;
+            let relative = file.path().strip_prefix(dir).unwrap_or(file.path());
+            let source = relative
+                .with_extension("")
+                .components()
+                .filter_map(|component| component.as_os_str().to_str())
+                .fold(alias.to_string(), |source, part| source + "/" + part);
+
+            for captures in export_re.captures_iter(&content) {
+                self.insert(RegistryEntry {
+                    name: captures[1].to_string(),
+                    module: "#imports".into(),
+                    source: source.clone(),
+                    kind: ImportKind::Function,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// `entry` を登録したレジストリを返す (`Registry::default()` や `builtin` から組み立てる用)
+    pub fn with_entry(mut self, entry: RegistryEntry) -> Self {
+        self.insert(entry);
+        self
+    }
+
+    /// `scan_exports` で `dir` の export を登録したレジストリを返す
+    pub fn with_exports(mut self, dir: &Path, alias: &str) -> Result<Self, Error> {
+        self.scan_exports(dir, alias)?;
+        Ok(self)
+    }
+
+    pub fn insert(&mut self, entry: RegistryEntry) {
+        self.entries.insert(entry.name.clone(), entry);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry> {
+        self.entries.get(name)
+    }
+}
+
+/// アプリ側とサーバー側のレジストリの組
+#[derive(Debug, Clone)]
+pub struct RegistrySet {
+    pub app: Registry,
+    pub server: Registry,
+}
+
+impl RegistrySet {
+    /// `root` をプロジェクトルートとしてレジストリを構築する
+    ///
+    /// `composables/` と `utils/` (Nuxt 4 では `app/` 以下) の直下の export をアプリ側に、
+    /// `server/utils/` 以下の export をサーバー側に登録する。
+    pub fn load(version: NuxtVersion, root: &Path) -> Result<Self, Error> {
+        let mut app = Registry::builtin(version, Context::App);
+        for src_dir in [root.to_path_buf(), root.join("app")] {
+            app.scan_top_level_exports(&src_dir.join("composables"), "~/composables")?;
+            app.scan_top_level_exports(&src_dir.join("utils"), "~/utils")?;
+        }
+
+        let mut server = Registry::builtin(version, Context::Server);
+        server.scan_exports(&root.join("server").join("utils"), "~~/server/utils")?;
+
+        Ok(Self { app, server })
+    }
+
+    /// 変更されたらレジストリを作り直すべきファイルか (`relative` は `root` からの相対パス)
+    ///
+    /// `load` が読むディレクトリに加えて、自動インポートの設定が変わりうる
+    /// `package.json`、`nuxt.config.ts`、`.nuxt/` の型定義も含める。
+    pub fn is_input(relative: &Path) -> bool {
+        const DIRS: &[&str] = &[
+            "composables",
+            "utils",
+            "app/composables",
+            "app/utils",
+            "server/utils",
+        ];
+        let file_name = relative.file_name().and_then(|name| name.to_str());
+        DIRS.iter().any(|dir| relative.starts_with(dir))
+            || matches!(file_name, Some("package.json" | "nuxt.config.ts"))
+            || (relative.starts_with(".nuxt")
+                && file_name.is_some_and(|name| name.ends_with(".d.ts")))
+    }
+
+    pub fn get(&self, context: Context) -> &Registry {
+        match context {
+            Context::App => &self.app,
+            Context::Server => &self.server,
+        }
+    }
+
+    /// `path` のファイルで使うレジストリ (`root` はプロジェクトルート)
+    pub fn for_path(&self, path: &Path, root: &Path) -> &Registry {
+        self.get(Context::from_path(path, root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_nuxt_version() {
+        assert_eq!("3.12".parse(), Ok(NuxtVersion::new(3, 12)));
+        assert_eq!("3.12.4".parse(), Ok(NuxtVersion::new(3, 12)));
+        assert_eq!("^3.8.2".parse(), Ok(NuxtVersion::new(3, 8)));
+        assert_eq!("~3".parse(), Ok(NuxtVersion::new(3, 0)));
+        assert!("latest".parse::<NuxtVersion>().is_err());
+    }
+
+    #[test]
+    fn test_builtin_registry_contains_core_imports() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+
+        for name in ["useRoute", "navigateTo", "useSeoMeta", "useCookie", "ref"] {
+            let entry = registry.get(name).expect("missing builtin import");
+            assert_eq!(entry.module, "#imports");
+            assert_eq!(entry.kind, ImportKind::Function);
+        }
+
+        let entry = registry
+            .get("ClientOnly")
+            .expect("missing builtin component");
+        assert_eq!(entry.module, "#components");
+        assert_eq!(entry.kind, ImportKind::Component);
+    }
+
+    #[test]
+    fn test_builtin_registry_respects_version() {
+        let old = Registry::builtin(NuxtVersion::new(3, 8), Context::App);
+        assert!(old.get("useFetch").is_some());
+        assert!(old.get("callOnce").is_none());
+        assert!(old.get("useId").is_none());
+
+        let new = Registry::builtin(NuxtVersion::new(3, 10), Context::App);
+        assert!(new.get("callOnce").is_some());
+        assert!(new.get("useId").is_some());
+    }
+
+    #[test]
+    fn test_registry_entry_module_for_style() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+
+        let cases = [
+            ("ref", "#imports", "vue"),
+            ("onBeforeRouteLeave", "#imports", "vue-router"),
+            ("useFetch", "#imports", "nuxt/app"),
+            ("NuxtLink", "#components", "#components"),
+        ];
+        for (name, virtual_module, source) in cases {
+            let entry = registry.get(name).expect("missing builtin import");
+            assert_eq!(entry.module_for(ImportStyle::Virtual), virtual_module);
+            assert_eq!(entry.module_for(ImportStyle::Source), source);
+        }
+
+        let server = Registry::builtin(NuxtVersion::LATEST, Context::Server);
+        let entry = server.get("readBody").expect("missing nitro import");
+        assert_eq!(entry.module_for(ImportStyle::Source), "h3");
+    }
+
+    #[test]
+    fn test_builtin_registry_classifies_macros() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+
+        let define_props = registry.get("defineProps").expect("missing macro");
+        assert_eq!(
+            define_props.kind,
+            ImportKind::Macro(MacroScope::ScriptSetup)
+        );
+
+        let page_meta = registry.get("definePageMeta").expect("missing macro");
+        assert_eq!(
+            page_meta.kind,
+            ImportKind::Macro(MacroScope::PageScriptSetup)
+        );
+    }
+
+    #[test]
+    fn test_script_scope_allows_macros() {
+        let component = ScriptScope {
+            setup: true,
+            page: false,
+        };
+        let page = ScriptScope {
+            setup: true,
+            page: true,
+        };
+        let plain = ScriptScope::default();
+
+        assert!(component.allows(MacroScope::ScriptSetup));
+        assert!(!component.allows(MacroScope::PageScriptSetup));
+        assert!(page.allows(MacroScope::PageScriptSetup));
+        assert!(!plain.allows(MacroScope::ScriptSetup));
+    }
+
+    #[test]
+    fn test_is_page_path() {
+        let root = Path::new("project");
+
+        assert!(is_page_path(Path::new("project/pages/index.vue"), root));
+        assert!(is_page_path(
+            Path::new("project/app/pages/users/[id].vue"),
+            root
+        ));
+        assert!(!is_page_path(
+            Path::new("project/components/Page.vue"),
+            root
+        ));
+    }
+
+    #[test]
+    fn test_registry_inputs() {
+        assert!(RegistrySet::is_input(Path::new(
+            "composables/useCounter.ts"
+        )));
+        assert!(RegistrySet::is_input(Path::new("app/utils/format.ts")));
+        assert!(RegistrySet::is_input(Path::new("server/utils/db.ts")));
+        assert!(RegistrySet::is_input(Path::new("nuxt.config.ts")));
+        assert!(RegistrySet::is_input(Path::new(".nuxt/imports.d.ts")));
+        assert!(!RegistrySet::is_input(Path::new(".nuxt/app.config.mjs")));
+        assert!(!RegistrySet::is_input(Path::new("pages/index.vue")));
+    }
+
+    #[test]
+    fn test_server_registry_uses_nitro_imports() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::Server);
+
+        for name in ["defineEventHandler", "readBody", "getQuery", "useStorage"] {
+            let entry = registry.get(name).expect("missing nitro import");
+            assert_eq!(entry.module, "#imports");
+        }
+        assert!(registry.get("useRuntimeConfig").is_some());
+        assert!(registry.get("useRoute").is_none());
+        assert!(registry.get("NuxtLink").is_none());
+    }
+
+    #[test]
+    fn test_context_from_path() {
+        let root = Path::new("project");
+
+        assert_eq!(
+            Context::from_path(Path::new("project/server/api/hello.ts"), root),
+            Context::Server
+        );
+        assert_eq!(
+            Context::from_path(Path::new("project/pages/index.vue"), root),
+            Context::App
+        );
+        assert_eq!(
+            Context::from_path(Path::new("project/components/server/Card.vue"), root),
+            Context::App
+        );
+    }
+
+    #[test]
+    fn test_scan_exports_registers_server_utils() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        fs::write(
+            dir.path().join("auth.ts"),
+            "export const requireUser = async (event) => {};\nexport function hashPassword() {}\nconst internal = 1;\n",
+        )
+        .expect("Failed to write server util");
+
+        let mut registry = Registry::default();
+        registry
+            .scan_exports(dir.path(), "~~/server/utils")
+            .unwrap();
+
+        let entry = registry.get("requireUser").expect("missing server util");
+        assert_eq!(entry.module, "#imports");
+        assert_eq!(entry.source, "~~/server/utils/auth");
+        assert!(registry.get("hashPassword").is_some());
+        assert!(registry.get("internal").is_none());
+    }
+
+    #[test]
+    fn test_load_registers_only_top_level_composables() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let composables = dir.path().join("composables");
+        fs::create_dir_all(composables.join("internal")).unwrap();
+        fs::create_dir_all(composables.join("cart")).unwrap();
+        fs::create_dir_all(dir.path().join("server/utils/db")).unwrap();
+        fs::write(
+            composables.join("useCounter.ts"),
+            "export const useCounter = () => {};\n",
+        )
+        .unwrap();
+        fs::write(
+            composables.join("internal/helper.ts"),
+            "export const useHelper = () => {};\n",
+        )
+        .unwrap();
+        fs::write(
+            composables.join("cart/index.ts"),
+            "export const useCart = () => {};\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("server/utils/db/client.ts"),
+            "export const useDb = () => {};\n",
+        )
+        .unwrap();
+
+        let registries = RegistrySet::load(NuxtVersion::LATEST, dir.path()).unwrap();
+        assert!(registries.app.get("useCounter").is_some());
+        assert_eq!(
+            registries.app.get("useCart").unwrap().source,
+            "~/composables/cart/index"
+        );
+        assert!(
+            registries.app.get("useHelper").is_none(),
+            "Nuxt does not auto-import nested composables"
+        );
+        assert_eq!(
+            registries.server.get("useDb").unwrap().source,
+            "~~/server/utils/db/client"
+        );
+    }
+
+    #[test]
+    fn test_build_registry() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        fs::write(
+            dir.path().join("useCart.ts"),
+            "export const useCart = () => {};\n",
+        )
+        .unwrap();
+
+        let registry = Registry::default()
+            .with_entry(RegistryEntry {
+                name: "useI18n".into(),
+                module: "#imports".into(),
+                source: "vue-i18n".into(),
+                kind: ImportKind::Function,
+            })
+            .with_exports(dir.path(), "~/composables")
+            .unwrap();
+
+        assert_eq!(registry.get("useI18n").unwrap().source, "vue-i18n");
+        assert_eq!(
+            registry.get("useCart").unwrap().source,
+            "~/composables/useCart"
+        );
+        assert!(registry.get("useState").is_none());
+        assert!(Registry::default()
+            .with_exports(&dir.path().join("missing"), "~/utils")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_detect_version_from_package_json() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{ "devDependencies": { "nuxt": "^3.11.2" } }"#,
+        )
+        .expect("Failed to write package.json");
+        let src = dir.path().join("src");
+        fs::create_dir(&src).expect("Failed to create src dir");
+
+        assert_eq!(
+            NuxtVersion::detect(&src).unwrap(),
+            Some(NuxtVersion::new(3, 11))
+        );
+
+        fs::write(dir.path().join("package.json"), "{ broken").unwrap();
+        assert!(matches!(
+            NuxtVersion::detect(&src),
+            Err(Error::Config { .. })
+        ));
+    }
+}
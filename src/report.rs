@@ -0,0 +1,568 @@
+use crate::diagnostic::Diagnostic;
+use crate::diff;
+use crate::error::Error;
+use crate::file_processor::{FileOutcome, FileResult, SkipReason};
+use crate::transformer::ImportChange;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+/// 結果の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 人が読むためのテキスト
+    Text,
+    /// 実行の最後に 1 つの JSON ドキュメントを出力する
+    Json,
+    /// 1 ファイルごとに 1 行の JSON を処理しながら出力し、最後に集計を出力する
+    Ndjson,
+    /// SARIF 2.1.0 (コードスキャン結果としてアップロードできる)
+    Sarif,
+    /// GitHub Actions のワークフローコマンド (`::warning file=...`)
+    Github,
+}
+
+/// 足りない明示的 import を表す SARIF のルール ID
+pub const MISSING_IMPORT_RULE: &str = "missing-explicit-import";
+
+#[derive(Debug, Serialize)]
+pub struct FileReport<'a> {
+    pub path: &'a Path,
+    /// `unchanged` / `skipped` / `updated` / `failed`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+    pub added_imports: &'a [ImportChange],
+    pub removed_imports: &'a [ImportChange],
+    pub errors: Vec<ErrorReport>,
+}
+
+/// レポートに書く 1 件のエラー
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// `Error::kind` の値 (`read` / `write` / `parse` / `codegen` / `config` / `registry` /
+    /// `journal` / `dirty-working-tree` / `git` / `watch` / `lsp`)
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+}
+
+impl From<&Error> for ErrorReport {
+    fn from(error: &Error) -> Self {
+        Self {
+            kind: error.kind(),
+            diagnostic: error.diagnostic(),
+        }
+    }
+}
+
+impl<'a> FileReport<'a> {
+    pub fn new(path: &'a Path, status: &'a FileResult) -> Self {
+        let skip_reason = match status {
+            Ok(FileOutcome::Skipped(reason)) => Some(*reason),
+            _ => None,
+        };
+        let (status, added_imports, removed_imports, errors): (_, &[_], &[_], _) = match status {
+            Ok(FileOutcome::Unchanged) => ("unchanged", &[], &[], vec![]),
+            Ok(FileOutcome::Skipped(_)) => ("skipped", &[], &[], vec![]),
+            Ok(FileOutcome::Changed {
+                added_imports,
+                removed_imports,
+                ..
+            }) => ("updated", added_imports, removed_imports, vec![]),
+            Err(error) => ("failed", &[], &[], vec![error.into()]),
+        };
+
+        Self {
+            path,
+            status,
+            skip_reason,
+            added_imports,
+            removed_imports,
+            errors,
+        }
+    }
+}
+
+/// 実行全体の集計
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    /// 走査した対象ファイルの数
+    pub files: usize,
+    pub unchanged: usize,
+    /// 理由ごとのスキップしたファイルの数
+    pub skipped: BTreeMap<SkipReason, usize>,
+    pub updated: usize,
+    pub failed: usize,
+    /// 失敗のうちパースエラーの数
+    pub parse_errors: usize,
+    pub added_imports: usize,
+    /// モジュールごとの追加した import の数
+    pub added_by_module: BTreeMap<String, usize>,
+    /// 名前ごとの追加した import の数
+    pub added_by_name: BTreeMap<String, usize>,
+    pub removed_imports: usize,
+    /// 実行にかかった時間 (ミリ秒)
+    pub elapsed_ms: u64,
+}
+
+impl Totals {
+    pub fn from_statuses(statuses: &[(PathBuf, FileResult)]) -> Self {
+        let mut totals = Self::default();
+        for (_, status) in statuses {
+            totals.record(status);
+        }
+        totals
+    }
+
+    /// 1 ファイル分の結果を集計に加える
+    pub fn record(&mut self, status: &FileResult) {
+        self.files += 1;
+        match status {
+            Ok(FileOutcome::Unchanged) => self.unchanged += 1,
+            Ok(FileOutcome::Skipped(reason)) => *self.skipped.entry(*reason).or_default() += 1,
+            Ok(FileOutcome::Changed {
+                added_imports,
+                removed_imports,
+                ..
+            }) => {
+                self.updated += 1;
+                self.added_imports += added_imports.len();
+                self.removed_imports += removed_imports.len();
+                for change in added_imports {
+                    *self
+                        .added_by_module
+                        .entry(change.module.clone())
+                        .or_default() += 1;
+                    *self.added_by_name.entry(change.name.clone()).or_default() += 1;
+                }
+            }
+            Err(error) => {
+                self.failed += 1;
+                if matches!(error, Error::Parse(_)) {
+                    self.parse_errors += 1;
+                }
+            }
+        }
+    }
+
+    /// 実行の最後に表示するテキストのサマリー
+    pub fn summary(&self) -> String {
+        let skipped: usize = self.skipped.values().sum();
+        let mut out = String::from("Summary:\n");
+        out += &format!("  Scanned:   {} file(s)\n", self.files);
+        out += &format!("  Unchanged: {}\n", self.unchanged);
+        out += &format!("  Skipped:   {}", skipped);
+        if skipped > 0 {
+            let reasons: Vec<_> = self
+                .skipped
+                .iter()
+                .map(|(reason, count)| format!("{}: {}", reason, count))
+                .collect();
+            out += &format!(" ({})", reasons.join(", "));
+        }
+        out += "\n";
+        out += &format!("  Updated:   {}\n", self.updated);
+        out += &format!(
+            "  Failed:    {} ({} parse error(s))\n",
+            self.failed, self.parse_errors
+        );
+        out += &format!("  Imports added:   {}\n", self.added_imports);
+        for (module, count) in &self.added_by_module {
+            out += &format!("    from \"{}\": {}\n", module, count);
+        }
+        // よく使われている名前から順に並べる
+        let mut names: Vec<_> = self.added_by_name.iter().collect();
+        names.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        for (name, count) in names {
+            out += &format!("    {}: {}\n", name, count);
+        }
+        out += &format!("  Imports removed: {}\n", self.removed_imports);
+        out += &format!("  Elapsed: {:.2}s\n", self.elapsed_ms as f64 / 1000.0);
+        out
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record<'a> {
+    File(FileReport<'a>),
+    Totals(&'a Totals),
+}
+
+/// NDJSON の 1 ファイル分の行
+pub fn ndjson_file(path: &Path, status: &FileResult) -> String {
+    serde_json::to_string(&Record::File(FileReport::new(path, status))).unwrap()
+}
+
+/// NDJSON の最後に出力する集計の行
+pub fn ndjson_totals(totals: &Totals) -> String {
+    serde_json::to_string(&Record::Totals(totals)).unwrap()
+}
+
+/// `--format json` で出力するドキュメント
+pub fn json_report(statuses: &[(PathBuf, FileResult)], totals: &Totals, dry_run: bool) -> String {
+    #[derive(Serialize)]
+    struct Report<'a> {
+        dry_run: bool,
+        files: Vec<FileReport<'a>>,
+        totals: &'a Totals,
+    }
+
+    let report = Report {
+        dry_run,
+        files: statuses
+            .iter()
+            .map(|(path, status)| FileReport::new(path, status))
+            .collect(),
+        totals,
+    };
+    serde_json::to_string_pretty(&report).unwrap()
+}
+
+/// 足りない import 1 件分のメッセージ
+pub fn missing_import_message(change: &ImportChange) -> String {
+    format!(
+        "`{0}` is auto-imported; add `import {{ {0} }} from \"{1}\"`",
+        change.name, change.module
+    )
+}
+
+/// 足りない import を最初に使っている位置ごとに 1 件の結果を持つ SARIF 2.1.0 ドキュメント
+pub fn sarif_report(statuses: &[(PathBuf, FileResult)]) -> String {
+    let mut results = Vec::new();
+    for (path, status) in statuses {
+        let uri = diff::patch_path(path);
+        match status {
+            Ok(FileOutcome::Unchanged | FileOutcome::Skipped(_)) => {}
+            Ok(FileOutcome::Changed { added_imports, .. }) => {
+                results.extend(added_imports.iter().map(|change| {
+                    json!({
+                        "ruleId": MISSING_IMPORT_RULE,
+                        "level": "warning",
+                        "message": { "text": missing_import_message(change) },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": uri },
+                                "region": { "startLine": change.line, "startColumn": change.column },
+                            },
+                        }],
+                    })
+                }));
+            }
+            Err(error) => {
+                let error = error.diagnostic();
+                let mut location = json!({ "artifactLocation": { "uri": uri } });
+                if error.line > 0 {
+                    location["region"] =
+                        json!({ "startLine": error.line, "startColumn": error.column });
+                }
+                results.push(json!({
+                    "level": "error",
+                    "message": { "text": error.message },
+                    "locations": [{ "physicalLocation": location }],
+                }));
+            }
+        }
+    }
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "denux",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/mikinovation/denux",
+                    "rules": [{
+                        "id": MISSING_IMPORT_RULE,
+                        "shortDescription": { "text": "Auto-imported name without an explicit import" },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&sarif).unwrap()
+}
+
+/// GitHub Actions のアノテーションとして出力するワークフローコマンド (1 行 1 件)
+pub fn github_annotations(statuses: &[(PathBuf, FileResult)]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (path, status) in statuses {
+        let file = escape_property(&diff::patch_path(path));
+        match status {
+            Ok(FileOutcome::Unchanged | FileOutcome::Skipped(_)) => {}
+            Ok(FileOutcome::Changed { added_imports, .. }) => {
+                lines.extend(added_imports.iter().map(|change| {
+                    format!(
+                        "::warning file={},line={},col={},title=Missing explicit import::{}",
+                        file,
+                        change.line,
+                        change.column,
+                        escape_data(&missing_import_message(change))
+                    )
+                }));
+            }
+            Err(error) => {
+                let error = error.diagnostic();
+                lines.push(if error.line > 0 {
+                    format!(
+                        "::error file={},line={},col={}::{}",
+                        file,
+                        error.line,
+                        error.column,
+                        escape_data(&error.message)
+                    )
+                } else {
+                    format!("::error file={}::{}", file, escape_data(&error.message))
+                });
+            }
+        }
+    }
+    lines
+}
+
+/// ワークフローコマンドのメッセージ部分のエスケープ
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// ワークフローコマンドのプロパティ値のエスケープ
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// テキスト形式で結果を表示する
+///
+/// `--check` は追加・削除する import を、`--dry-run` は diff を、`--verbose` は更新したファイルを表示する。
+/// 失敗したファイルはソースの抜粋付きで標準エラー出力に表示する。
+pub fn print_text(statuses: &[(PathBuf, FileResult)], check: bool, dry_run: bool, verbose: bool) {
+    let color = io::stdout().is_terminal();
+
+    for (path, status) in statuses {
+        match status {
+            Ok(FileOutcome::Unchanged | FileOutcome::Skipped(_)) => {}
+            Err(error) => eprint!("{}", error.diagnostic().render(path)),
+            Ok(FileOutcome::Changed {
+                added_imports,
+                removed_imports,
+                original,
+                updated,
+            }) => {
+                if check {
+                    println!("Would update: {:?}", path);
+                    for change in added_imports {
+                        println!(
+                            "  + import {{ {} }} from \"{}\" (first used at {}:{})",
+                            change.name, change.module, change.line, change.column
+                        );
+                    }
+                    for change in removed_imports {
+                        println!(
+                            "  - import {{ {} }} from \"{}\"",
+                            change.name, change.module
+                        );
+                    }
+                } else if dry_run {
+                    print!("{}", diff::unified_diff(path, original, updated, color));
+                } else if verbose {
+                    println!("Updated: {:?}", path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_processor::process_script_source;
+    use crate::parser::{process_script_setup, ScriptBlock};
+    use crate::registry::{Context, NuxtVersion, Registry};
+    use crate::transformer::TransformOptions;
+
+    fn statuses() -> Vec<(PathBuf, FileResult)> {
+        vec![
+            (PathBuf::from("a.ts"), Ok(FileOutcome::Unchanged)),
+            (
+                PathBuf::from("b.vue"),
+                Ok(FileOutcome::Changed {
+                    added_imports: vec![ImportChange {
+                        name: "useFetch".into(),
+                        module: "#imports".into(),
+                        line: 3,
+                        column: 14,
+                        ..Default::default()
+                    }],
+                    removed_imports: vec![],
+                    original: "before".into(),
+                    updated: "after".into(),
+                }),
+            ),
+            (
+                PathBuf::from("c.ts"),
+                Err(Error::Parse(Diagnostic::new("Unexpected eof", 2, 5))),
+            ),
+            (
+                PathBuf::from("d.vue"),
+                Ok(FileOutcome::Skipped(SkipReason::NoScript)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_totals_from_statuses() {
+        let totals = Totals::from_statuses(&statuses());
+
+        assert_eq!(
+            totals,
+            Totals {
+                files: 4,
+                unchanged: 1,
+                skipped: BTreeMap::from([(SkipReason::NoScript, 1)]),
+                updated: 1,
+                failed: 1,
+                parse_errors: 1,
+                added_imports: 1,
+                added_by_module: BTreeMap::from([("#imports".into(), 1)]),
+                added_by_name: BTreeMap::from([("useFetch".into(), 1)]),
+                removed_imports: 0,
+                elapsed_ms: 0,
+            }
+        );
+    }
+
+    /// Nuxt プラグインのように `declare module` ブロックを含むファイルを実際に変換した結果
+    fn plugin_statuses() -> Vec<(PathBuf, FileResult)> {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let path = PathBuf::from("plugins/hello.ts");
+        let source = "export default defineNuxtPlugin(() => {\n  const route = useRoute();\n});\ndeclare module '#app' {\n  interface NuxtApp {\n    $hello: string;\n  }\n}\n";
+        let status = process_script_source(&path, source, |block: &ScriptBlock| {
+            process_script_setup(block, &registry, TransformOptions::default())
+        });
+        vec![(path, status)]
+    }
+
+    #[test]
+    fn test_json_report_lists_each_added_import_once() {
+        let statuses = plugin_statuses();
+        let totals = Totals::from_statuses(&statuses);
+        let report: serde_json::Value =
+            serde_json::from_str(&json_report(&statuses, &totals, true)).unwrap();
+
+        let names: Vec<&str> = report["files"][0]["added_imports"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|change| change["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["defineNuxtPlugin", "useRoute"]);
+        assert_eq!(github_annotations(&statuses).len(), 2);
+    }
+
+    #[test]
+    fn test_totals_count_each_added_import_once() {
+        let totals = Totals::from_statuses(&plugin_statuses());
+
+        assert_eq!(totals.added_imports, 2);
+        assert_eq!(
+            totals.added_by_name,
+            BTreeMap::from([("defineNuxtPlugin".into(), 1), ("useRoute".into(), 1)])
+        );
+        assert!(totals.summary().contains("  Imports added:   2\n"));
+    }
+
+    #[test]
+    fn test_totals_summary() {
+        let mut totals = Totals::from_statuses(&statuses());
+        totals.elapsed_ms = 1234;
+
+        assert_eq!(
+            totals.summary(),
+            "Summary:\n  Scanned:   4 file(s)\n  Unchanged: 1\n  Skipped:   1 (no <script>: 1)\n  Updated:   1\n  Failed:    1 (1 parse error(s))\n  Imports added:   1\n    from \"#imports\": 1\n    useFetch: 1\n  Imports removed: 0\n  Elapsed: 1.23s\n"
+        );
+    }
+
+    #[test]
+    fn test_json_report() {
+        let statuses = statuses();
+        let totals = Totals::from_statuses(&statuses);
+        let report: serde_json::Value =
+            serde_json::from_str(&json_report(&statuses, &totals, true)).unwrap();
+
+        assert_eq!(report["dry_run"], true);
+        assert_eq!(report["files"][1]["path"], "b.vue");
+        assert_eq!(report["files"][1]["status"], "updated");
+        assert_eq!(
+            report["files"][1]["added_imports"][0],
+            serde_json::json!({ "name": "useFetch", "module": "#imports", "line": 3, "column": 14 })
+        );
+        assert_eq!(report["files"][2]["status"], "failed");
+        assert_eq!(
+            report["files"][2]["errors"][0],
+            json!({ "kind": "parse", "message": "failed to parse: Unexpected eof", "line": 2, "column": 5 })
+        );
+        assert_eq!(report["files"][3]["skip_reason"], "no-script");
+        assert_eq!(report["totals"]["files"], 4);
+        assert_eq!(report["totals"]["skipped"]["no-script"], 1);
+    }
+
+    #[test]
+    fn test_ndjson_lines() {
+        let statuses = statuses();
+        let line = ndjson_file(&statuses[0].0, &statuses[0].1);
+        assert_eq!(
+            line,
+            r#"{"type":"file","path":"a.ts","status":"unchanged","added_imports":[],"removed_imports":[],"errors":[]}"#
+        );
+
+        let totals = ndjson_totals(&Totals::from_statuses(&statuses));
+        assert!(totals.starts_with(r#"{"type":"totals","files":4,"#));
+    }
+
+    #[test]
+    fn test_sarif_report() {
+        let sarif: serde_json::Value = serde_json::from_str(&sarif_report(&statuses())).unwrap();
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["ruleId"], MISSING_IMPORT_RULE);
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "b.vue" },
+                "region": { "startLine": 3, "startColumn": 14 },
+            })
+        );
+        assert_eq!(results[1]["level"], "error");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            json!({ "startLine": 2, "startColumn": 5 })
+        );
+    }
+
+    #[test]
+    fn test_github_annotations() {
+        assert_eq!(
+            github_annotations(&statuses()),
+            vec![
+                "::warning file=b.vue,line=3,col=14,title=Missing explicit import::`useFetch` is auto-imported; add `import { useFetch } from \"#imports\"`",
+                "::error file=c.ts,line=2,col=5::failed to parse: Unexpected eof",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_property() {
+        assert_eq!(escape_property("a,b:c%\n"), "a%2Cb%3Ac%25%0A");
+    }
+}
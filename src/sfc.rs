@@ -0,0 +1,89 @@
+use crate::parser::ScriptLang;
+use regex::Regex;
+use std::ops::Range;
+
+/// `.vue` ファイル (SFC) の `<template>` と `<script>` ブロック
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sfc<'a> {
+    /// `<template>` の中身
+    pub template: Option<&'a str>,
+    /// `<script>` と `<script setup>` ブロック (出現順)
+    pub scripts: Vec<SfcScript<'a>>,
+}
+
+/// `<script>` ブロック 1 つの中身と種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SfcScript<'a> {
+    pub content: &'a str,
+    /// ファイル内での中身のバイト範囲 (開始タグと終了タグは含まない)
+    pub range: Range<usize>,
+    /// `lang` 属性から決めた言語 (なければ JavaScript)
+    pub lang: ScriptLang,
+    /// `<script setup>` か
+    pub setup: bool,
+}
+
+/// `.vue` ファイルからブロックを取り出す
+pub fn parse_sfc(content: &str) -> Sfc<'_> {
+    let template_re = Regex::new(r"(?s)<template[^>]*>(.*)</template>").unwrap();
+    let script_re = Regex::new(r"(?s)<script\b([^>]*)>(.*?)</script>").unwrap();
+    let setup_re = Regex::new(r"(?:^|\s)setup(?:\s|=|/|$)").unwrap();
+    let lang_re = Regex::new(r#"\blang\s*=\s*["']?(\w+)"#).unwrap();
+
+    let template = template_re
+        .captures(content)
+        .and_then(|captures| captures.get(1))
+        .map(|template| template.as_str());
+    let scripts = script_re
+        .captures_iter(content)
+        .map(|captures| {
+            let attributes = captures.get(1).unwrap().as_str();
+            let body = captures.get(2).unwrap();
+            SfcScript {
+                content: body.as_str(),
+                range: body.range(),
+                lang: lang_re
+                    .captures(attributes)
+                    .and_then(|captures| ScriptLang::from_extension(&captures[1]))
+                    .unwrap_or_default(),
+                setup: setup_re.is_match(attributes),
+            }
+        })
+        .collect();
+
+    Sfc { template, scripts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sfc() {
+        let content = r#"<template>
+  <NuxtLink to="/" />
+</template>
+<script lang="ts">
+export default {};
+</script>
+<script setup>
+const route = useRoute();
+</script>
+"#;
+        let sfc = parse_sfc(content);
+
+        assert_eq!(sfc.template, Some("\n  <NuxtLink to=\"/\" />\n"));
+        assert_eq!(sfc.scripts.len(), 2);
+        assert_eq!(sfc.scripts[0].content, "\nexport default {};\n");
+        assert_eq!(sfc.scripts[0].lang, ScriptLang::Ts);
+        assert!(!sfc.scripts[0].setup);
+        assert_eq!(sfc.scripts[1].lang, ScriptLang::Js);
+        assert!(sfc.scripts[1].setup);
+        assert_eq!(
+            &content[sfc.scripts[1].range.clone()],
+            "\nconst route = useRoute();\n"
+        );
+
+        assert_eq!(parse_sfc("<style></style>"), Sfc::default());
+    }
+}
@@ -0,0 +1,94 @@
+use regex::Regex;
+use std::collections::HashSet;
+
+/// `<template>` 内で参照されている名前を集める
+///
+/// コンポーネントのタグ名 (`nuxt-link` は `NuxtLink` としても登録) と、
+/// `{{ }}` やディレクティブ (`:prop`, `@event`, `v-if` など) の式に現れる識別子を返す。
+pub fn template_references(template: &str) -> HashSet<String> {
+    let tag_re = Regex::new(r"<([A-Za-z][\w\-.]*)").unwrap();
+    let mustache_re = Regex::new(r"(?s)\{\{(.*?)\}\}").unwrap();
+    let directive_re =
+        Regex::new(r#"(?:v-[\w\-:.\[\]]+|[:@#][\w\-:.\[\]]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#)
+            .unwrap();
+    let ident_re = Regex::new(r"[A-Za-z_$][\w$]*").unwrap();
+
+    let mut references = HashSet::new();
+
+    for captures in tag_re.captures_iter(template) {
+        let tag = &captures[1];
+        references.insert(tag.to_string());
+        if tag.contains('-') {
+            references.insert(kebab_to_pascal(tag));
+        }
+    }
+
+    let expressions = mustache_re
+        .captures_iter(template)
+        .filter_map(|captures| captures.get(1))
+        .chain(
+            directive_re
+                .captures_iter(template)
+                .filter_map(|captures| captures.get(1).or_else(|| captures.get(2))),
+        );
+    for expression in expressions {
+        for ident in ident_re.find_iter(expression.as_str()) {
+            references.insert(ident.as_str().to_string());
+        }
+    }
+
+    references
+}
+
+fn kebab_to_pascal(name: &str) -> String {
+    name.split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_references_collects_components() {
+        let template = r#"
+        <div>
+            <nuxt-link to="/about">About</nuxt-link>
+            <ClientOnly><NuxtPage /></ClientOnly>
+        </div>
+        "#;
+        let references = template_references(template);
+
+        for name in ["NuxtLink", "nuxt-link", "ClientOnly", "NuxtPage", "div"] {
+            assert!(references.contains(name), "{} should be referenced", name);
+        }
+    }
+
+    #[test]
+    fn test_template_references_collects_expression_identifiers() {
+        let template = r#"
+        <p v-if="isVisible">{{ formatDate(createdAt) }}</p>
+        <button @click='refresh()' :class="{ active: isActive }">Reload</button>
+        <span>useFetch is only text here</span>
+        "#;
+        let references = template_references(template);
+
+        for name in [
+            "isVisible",
+            "formatDate",
+            "createdAt",
+            "refresh",
+            "isActive",
+        ] {
+            assert!(references.contains(name), "{} should be referenced", name);
+        }
+        assert!(!references.contains("useFetch"));
+    }
+}
@@ -1,88 +1,182 @@
+use crate::registry::{ImportKind, ImportStyle, Registry, RegistryEntry, ScriptScope};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use swc_common::Span;
 use swc_ecma_ast::*;
-use swc_ecma_visit::{VisitMut, VisitMutWith};
+use swc_ecma_visit::{Visit, VisitMut, VisitMutWith, VisitWith};
 
-pub struct ImportInserter {
-    pub imports: Vec<(String, String)>,
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransformOptions {
+    pub scope: ScriptScope,
+    pub style: ImportStyle,
+    /// 明示的な import を追加する代わりに、自動インポートされる名前の import を取り除く
+    pub reverse: bool,
+    /// 使われていない自動インポート由来の import を取り除く
+    pub remove_unused: bool,
+}
+
+/// 追加・削除した import の名前とインポート元
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportChange {
+    pub name: String,
+    pub module: String,
+    /// 追加なら最初に使われた位置、削除なら import 指定子の位置
+    #[serde(skip)]
+    pub span: Span,
+    /// `span` の 1 始まりの行 (位置を解決するまでは 0)
+    pub line: usize,
+    /// `span` の 1 始まりの列 (位置を解決するまでは 0)
+    pub column: usize,
+}
+
+impl ImportChange {
+    pub fn new(name: impl Into<String>, module: impl Into<String>, span: Span) -> Self {
+        Self {
+            name: name.into(),
+            module: module.into(),
+            span,
+            line: 0,
+            column: 0,
+        }
+    }
+}
+
+pub struct ImportInserter<'a> {
+    pub registry: &'a Registry,
+    pub options: TransformOptions,
     pub existing_imports: Vec<(String, String)>,
+    /// 同じ SFC のほかの `<script>` で import されている名前
+    pub sibling_imports: &'a [String],
     pub used_functions: Vec<String>,
+    /// 名前が最初に使われた位置
+    pub first_usages: HashMap<String, Span>,
+    pub added_imports: Vec<ImportChange>,
+    /// 既存の `#imports` / `#components` からの import を書き換えたかどうか
+    pub rewrote_imports: bool,
+    /// 使える場所の外で呼ばれたコンパイラマクロ
+    pub misused_macros: Vec<String>,
+    /// `ImportStyle::Source` でも実際のモジュールがないため、仮想モジュールから import した名前
+    pub virtual_imports: Vec<String>,
 }
 
-impl ImportInserter {
-    pub fn new() -> Self {
+impl<'a> ImportInserter<'a> {
+    pub fn new(registry: &'a Registry, options: TransformOptions) -> Self {
         Self {
-            imports: vec![
-                ("defineNuxtComponent".into(), "#imports".into()),
-                ("useState".into(), "#imports".into()),
-                ("useRuntimeConfig".into(), "#imports".into()),
-                ("useFetch".into(), "#imports".into()),
-                ("NuxtLink".into(), "#components".into()),
-                ("Suspense".into(), "#components".into()),
-                ("NuxtLayout".into(), "#components".into()),
-                ("NuxtPage".into(), "#components".into()),
-            ],
+            registry,
+            options,
             existing_imports: vec![],
+            sibling_imports: &[],
             used_functions: vec![],
+            first_usages: HashMap::new(),
+            added_imports: vec![],
+            rewrote_imports: false,
+            misused_macros: vec![],
+            virtual_imports: vec![],
         }
     }
+
+    fn record_usage(&mut self, name: String, span: Span) {
+        if !self.used_functions.contains(&name) {
+            self.first_usages.insert(name.clone(), span);
+            self.used_functions.push(name);
+        }
+    }
+
+    fn is_imported(&self, name: &str) -> bool {
+        self.existing_imports
+            .iter()
+            .any(|(import_name, _)| import_name == name)
+            || self
+                .sibling_imports
+                .iter()
+                .any(|import_name| import_name == name)
+    }
 }
 
-impl VisitMut for ImportInserter {
+impl VisitMut for ImportInserter<'_> {
     fn visit_mut_module_items(&mut self, items: &mut Vec<ModuleItem>) {
+        if self.options.style == ImportStyle::Source {
+            self.rewrote_imports = expand_virtual_imports(items, self.registry);
+        }
+
         for item in items.iter() {
             if let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item {
                 let module_name = import.src.value.to_string();
 
                 for specifier in &import.specifiers {
-                    if let ImportSpecifier::Named(named) = specifier {
-                        let import_name = named.local.sym.to_string();
-                        self.existing_imports
-                            .push((import_name.clone(), module_name.clone()));
-                    }
+                    let local = match specifier {
+                        ImportSpecifier::Named(named) => &named.local,
+                        ImportSpecifier::Default(default) => &default.local,
+                        ImportSpecifier::Namespace(namespace) => &namespace.local,
+                    };
+                    self.existing_imports
+                        .push((local.sym.to_string(), module_name.clone()));
                 }
             }
         }
 
+        // スクリプト内で宣言された名前は自動インポートではない (import すると宣言が重複する)
+        let mut declared = DeclaredNames::default();
+        for item in items.iter() {
+            item.visit_with(&mut declared);
+        }
+
         // 使用されている関数やコンポーネントをリストアップ
         for item in items.iter_mut() {
             item.visit_mut_children_with(self);
         }
 
-        // 必要な `import` を整理
-        let mut needed_imports: std::collections::HashMap<String, Vec<String>> =
-            std::collections::HashMap::new();
+        // 必要な `import` をモジュールごとに、初めて使われた順に整理
+        let mut needed_imports: Vec<(String, Vec<String>)> = vec![];
 
-        for (func_name, module) in &self.imports {
-            if self.used_functions.contains(func_name)
-                && !self
-                    .existing_imports
-                    .contains(&(func_name.clone(), module.clone()))
-            {
-                needed_imports
-                    .entry(module.clone())
-                    .or_default()
-                    .push(func_name.clone());
+        for func_name in &self.used_functions {
+            if declared.0.contains(func_name) {
+                continue;
+            }
+            let Some(entry) = self.registry.get(func_name) else {
+                continue;
+            };
+            // コンパイラマクロはインポートせず、使える場所の外なら報告する
+            if let ImportKind::Macro(macro_scope) = entry.kind {
+                if !self.options.scope.allows(macro_scope) {
+                    self.misused_macros.push(func_name.clone());
+                }
+                continue;
+            }
+            if self.is_imported(func_name) {
+                continue;
             }
-        }
 
-        // script 内に import 文を追加
-        let mut new_imports = vec![];
-        for (module, funcs) in needed_imports {
-            let import_stmt = format!("import {{ {} }} from \"{}\";", funcs.join(", "), module);
-            new_imports.push(import_stmt);
+            let module = entry.module_for(self.options.style);
+            // Nuxt のコンポーネントは公開されたモジュールから名前付きで import できない
+            if self.options.style == ImportStyle::Source && module.starts_with('#') {
+                self.virtual_imports.push(func_name.clone());
+            }
+            let span = self
+                .first_usages
+                .get(func_name)
+                .copied()
+                .unwrap_or_default();
+            self.added_imports
+                .push(ImportChange::new(func_name.as_str(), module, span));
+            match needed_imports.iter_mut().find(|(m, _)| m == module) {
+                Some((_, funcs)) => funcs.push(func_name.clone()),
+                None => needed_imports.push((module.to_string(), vec![func_name.clone()])),
+            }
         }
 
-        // 追加された import 文を script 内に挿入
-        if !new_imports.is_empty() {
-            let import_block = new_imports.join("\n") + "\n";
-            let import_stmt = ModuleItem::Stmt(Stmt::Expr(ExprStmt {
-                span: Default::default(),
-                expr: Box::new(Expr::Lit(Lit::Str(Str {
-                    span: Default::default(),
-                    value: import_block.into(),
-                    raw: None,
-                }))),
-            }));
-            items.insert(0, import_stmt);
+        // 追加する import 文を script の先頭に挿入
+        let new_imports = needed_imports
+            .into_iter()
+            .map(|(module, funcs)| import_decl(&module, &funcs));
+        items.splice(0..0, new_imports);
+    }
+
+    // `declare module '#app' { ... }` の中では使われている名前を集めるだけで、
+    // import の追加や書き換えは先頭のモジュールでだけ行う
+    fn visit_mut_ts_module_block(&mut self, block: &mut TsModuleBlock) {
+        for item in block.body.iter_mut() {
+            item.visit_mut_children_with(self);
         }
     }
 
@@ -93,10 +187,7 @@ impl VisitMut for ImportInserter {
         }) = expr
         {
             if let Expr::Ident(ident) = &**boxed_expr {
-                let func_name = ident.sym.to_string();
-                if !self.used_functions.contains(&func_name) {
-                    self.used_functions.push(func_name);
-                }
+                self.record_usage(ident.sym.to_string(), ident.span);
             }
         }
 
@@ -104,45 +195,414 @@ impl VisitMut for ImportInserter {
     }
 
     fn visit_mut_jsx_element(&mut self, jsx: &mut JSXElement) {
-        if let JSXElementName::Ident(ident) = &jsx.opening.name {
-            let component_name = ident.sym.to_string();
-            let components = ["NuxtLink", "Suspense", "NuxtLayout", "NuxtPage"];
+        // `<NuxtLink>` はコンポーネントとして、`<Foo.Bar>` は先頭の `Foo` を使っているとみなす
+        let used = match &jsx.opening.name {
+            JSXElementName::Ident(ident) => self
+                .registry
+                .get(ident.sym.as_ref())
+                .filter(|entry| entry.kind == ImportKind::Component)
+                .map(|_| ident),
+            JSXElementName::JSXMemberExpr(member) => {
+                let root = jsx_member_root(member);
+                self.registry
+                    .get(root.sym.as_ref())
+                    .filter(|entry| !matches!(entry.kind, ImportKind::Macro(_)))
+                    .map(|_| root)
+            }
+            // `<svg:rect>` のような名前空間つきの名前はコンポーネントではない
+            JSXElementName::JSXNamespacedName(_) => None,
+        };
+        if let Some(ident) = used {
+            self.record_usage(ident.sym.to_string(), ident.span);
+        }
+
+        jsx.visit_mut_children_with(self);
+    }
+}
 
-            if components.contains(&component_name.as_str())
-                && !self.used_functions.contains(&component_name)
+/// スクリプト内のどこかで宣言されている値の名前 (関数、クラス、変数、引数、`catch` の変数)
+///
+/// スコープは区別しないので、入れ子の関数の引数と同じ名前も自動インポートしない。
+/// import が足りないままになることはあっても、宣言が重複するコードは作らない。
+#[derive(Default)]
+struct DeclaredNames(HashSet<String>);
+
+impl Visit for DeclaredNames {
+    fn visit_binding_ident(&mut self, ident: &BindingIdent) {
+        self.0.insert(ident.id.sym.to_string());
+    }
+
+    fn visit_fn_decl(&mut self, decl: &FnDecl) {
+        self.0.insert(decl.ident.sym.to_string());
+        decl.visit_children_with(self);
+    }
+
+    fn visit_fn_expr(&mut self, expr: &FnExpr) {
+        if let Some(ident) = &expr.ident {
+            self.0.insert(ident.sym.to_string());
+        }
+        expr.visit_children_with(self);
+    }
+
+    fn visit_class_decl(&mut self, decl: &ClassDecl) {
+        self.0.insert(decl.ident.sym.to_string());
+        decl.visit_children_with(self);
+    }
+
+    fn visit_class_expr(&mut self, expr: &ClassExpr) {
+        if let Some(ident) = &expr.ident {
+            self.0.insert(ident.sym.to_string());
+        }
+        expr.visit_children_with(self);
+    }
+
+    fn visit_ts_enum_decl(&mut self, decl: &TsEnumDecl) {
+        self.0.insert(decl.id.sym.to_string());
+    }
+}
+
+/// `<Foo.Bar.Baz>` の `Foo`
+fn jsx_member_root(member: &JSXMemberExpr) -> &Ident {
+    match &member.obj {
+        JSXObject::Ident(ident) => ident,
+        JSXObject::JSXMemberExpr(member) => jsx_member_root(member),
+    }
+}
+
+/// Nuxt が自動インポートする名前の明示的な import を取り除く (`ImportInserter` の逆変換)
+///
+/// 型のみの import、副作用のための import、別名をつけた import は残す。
+pub struct ImportRemover<'a> {
+    pub registry: &'a Registry,
+    pub options: TransformOptions,
+    pub removed_imports: Vec<ImportChange>,
+}
+
+impl<'a> ImportRemover<'a> {
+    pub fn new(registry: &'a Registry, options: TransformOptions) -> Self {
+        Self {
+            registry,
+            options,
+            removed_imports: vec![],
+        }
+    }
+
+    fn is_auto_imported(&self, named: &ImportNamedSpecifier, module: &str) -> bool {
+        if named.is_type_only || imported_name(named) != named.local.sym.as_str() {
+            return false;
+        }
+
+        let Some(entry) = registry_entry_for(self.registry, named, module) else {
+            return false;
+        };
+        if let ImportKind::Macro(macro_scope) = entry.kind {
+            if !self.options.scope.allows(macro_scope) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl VisitMut for ImportRemover<'_> {
+    fn visit_mut_module_items(&mut self, items: &mut Vec<ModuleItem>) {
+        items.retain_mut(|item| {
+            let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item else {
+                return true;
+            };
+            if import.type_only || import.specifiers.is_empty() {
+                return true;
+            }
+
+            let module = import.src.value.to_string();
+            import.specifiers.retain(|specifier| match specifier {
+                ImportSpecifier::Named(named) if self.is_auto_imported(named, &module) => {
+                    self.removed_imports.push(ImportChange::new(
+                        named.local.sym.as_str(),
+                        module.as_str(),
+                        named.span,
+                    ));
+                    false
+                }
+                _ => true,
+            });
+
+            !import.specifiers.is_empty()
+        });
+    }
+}
+
+/// 自動インポート由来のモジュールから import されているのに、使われていない名前を取り除く
+///
+/// スクリプト内の識別子に加えて `template_references` に含まれる名前も使われているとみなす。
+pub struct UnusedImportRemover<'a> {
+    pub registry: &'a Registry,
+    /// テンプレートや同じ SFC のほかの `<script>` で使われている名前
+    pub template_references: HashSet<String>,
+    pub removed_imports: Vec<ImportChange>,
+}
+
+impl<'a> UnusedImportRemover<'a> {
+    pub fn new(registry: &'a Registry, template_references: HashSet<String>) -> Self {
+        Self {
+            registry,
+            template_references,
+            removed_imports: vec![],
+        }
+    }
+}
+
+impl VisitMut for UnusedImportRemover<'_> {
+    fn visit_mut_module_items(&mut self, items: &mut Vec<ModuleItem>) {
+        let mut references = referenced_names(items);
+        references.extend(self.template_references.iter().cloned());
+
+        items.retain_mut(|item| {
+            let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item else {
+                return true;
+            };
+            if import.type_only || import.specifiers.is_empty() {
+                return true;
+            }
+
+            let module = import.src.value.to_string();
+            import.specifiers.retain(|specifier| match specifier {
+                ImportSpecifier::Named(named)
+                    if !named.is_type_only
+                        && !references.contains(named.local.sym.as_str())
+                        && registry_entry_for(self.registry, named, &module).is_some() =>
+                {
+                    self.removed_imports.push(ImportChange::new(
+                        named.local.sym.as_str(),
+                        module.as_str(),
+                        named.span,
+                    ));
+                    false
+                }
+                _ => true,
+            });
+
+            !import.specifiers.is_empty()
+        });
+    }
+}
+
+/// import 宣言以外に現れる識別子 (使われているとみなす名前)
+pub fn referenced_names(items: &[ModuleItem]) -> HashSet<String> {
+    let mut collector = IdentCollector::default();
+    for item in items {
+        if !matches!(item, ModuleItem::ModuleDecl(ModuleDecl::Import(_))) {
+            item.visit_with(&mut collector);
+        }
+    }
+    collector.idents
+}
+
+#[derive(Default)]
+struct IdentCollector {
+    idents: HashSet<String>,
+}
+
+impl Visit for IdentCollector {
+    fn visit_ident(&mut self, ident: &Ident) {
+        self.idents.insert(ident.sym.to_string());
+    }
+}
+
+fn imported_name(named: &ImportNamedSpecifier) -> &str {
+    match &named.imported {
+        Some(ModuleExportName::Ident(ident)) => &ident.sym,
+        Some(ModuleExportName::Str(name)) => &name.value,
+        None => &named.local.sym,
+    }
+}
+
+/// `module` からの名前付き import が、レジストリの自動インポートに当たればそのエントリを返す
+fn registry_entry_for<'r>(
+    registry: &'r Registry,
+    named: &ImportNamedSpecifier,
+    module: &str,
+) -> Option<&'r RegistryEntry> {
+    registry
+        .get(imported_name(named))
+        .filter(|entry| entry.module == module || entry.source == module)
+}
+
+fn is_virtual_module(module: &str) -> bool {
+    matches!(module, "#imports" | "#components")
+}
+
+/// `#imports` / `#components` からの import を、名前ごとに実際のモジュールからの import へ書き換える
+///
+/// レジストリにない名前は元の仮想モジュールからの import のまま残す。
+/// 1 つでも書き換えた場合は `true` を返す。
+pub fn expand_virtual_imports(items: &mut Vec<ModuleItem>, registry: &Registry) -> bool {
+    let mut expanded = Vec::with_capacity(items.len());
+    let mut rewritten = false;
+
+    for item in items.drain(..) {
+        match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import))
+                if is_virtual_module(&import.src.value) && !import.specifiers.is_empty() =>
             {
-                self.used_functions.push(component_name);
+                let (split, changed) = split_import_by_source(import, registry);
+                expanded.extend(split);
+                rewritten |= changed;
             }
+            item => expanded.push(item),
         }
+    }
 
-        jsx.visit_mut_children_with(self);
+    *items = expanded;
+    rewritten
+}
+
+fn split_import_by_source(import: ImportDecl, registry: &Registry) -> (Vec<ModuleItem>, bool) {
+    let ImportDecl {
+        span,
+        specifiers,
+        src,
+        type_only,
+        with,
+        phase,
+    } = import;
+    let virtual_module = src.value.to_string();
+
+    let mut groups: Vec<(String, Vec<ImportSpecifier>)> = vec![];
+    for specifier in specifiers {
+        let source = match &specifier {
+            ImportSpecifier::Named(named) => registry
+                .get(imported_name(named))
+                .filter(|entry| entry.module == virtual_module)
+                .map(|entry| entry.source.clone()),
+            _ => None,
+        }
+        .unwrap_or_else(|| virtual_module.clone());
+
+        match groups.iter_mut().find(|(module, _)| *module == source) {
+            Some((_, group)) => group.push(specifier),
+            None => groups.push((source, vec![specifier])),
+        }
     }
+
+    let changed = groups.iter().any(|(module, _)| *module != virtual_module);
+    let split = groups
+        .into_iter()
+        .map(|(module, specifiers)| {
+            let src = if module == virtual_module {
+                src.clone()
+            } else {
+                Box::new(Str::from(module))
+            };
+            ModuleItem::ModuleDecl(ModuleDecl::Import(ImportDecl {
+                span,
+                specifiers,
+                src,
+                type_only,
+                with: with.clone(),
+                phase,
+            }))
+        })
+        .collect();
+
+    (split, changed)
+}
+
+/// `import { a, b } from "module";` を組み立てる
+fn import_decl(module: &str, names: &[String]) -> ModuleItem {
+    let specifiers = names
+        .iter()
+        .map(|name| {
+            ImportSpecifier::Named(ImportNamedSpecifier {
+                span: Default::default(),
+                local: Ident::new_no_ctxt(name.as_str().into(), Default::default()),
+                imported: None,
+                is_type_only: false,
+            })
+        })
+        .collect();
+
+    ModuleItem::ModuleDecl(ModuleDecl::Import(ImportDecl {
+        span: Default::default(),
+        specifiers,
+        src: Box::new(Str::from(module)),
+        type_only: false,
+        with: None,
+        phase: Default::default(),
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::registry::{Context, NuxtVersion};
     use swc_common::{sync::Lrc, SourceMap};
     use swc_ecma_codegen::{text_writer::JsWriter, Emitter};
-    use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
+    use swc_ecma_parser::{lexer::Lexer, EsSyntax, Parser, StringInput, Syntax, TsSyntax};
     use swc_ecma_visit::VisitMutWith;
 
     fn apply_transform(source: &str) -> String {
+        apply_transform_with(source, TransformOptions::default()).0
+    }
+
+    fn apply_transform_in(source: &str, scope: ScriptScope) -> (String, Vec<String>) {
+        apply_transform_with(
+            source,
+            TransformOptions {
+                scope,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn apply_transform_with(source: &str, options: TransformOptions) -> (String, Vec<String>) {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let mut inserter = ImportInserter::new(&registry, options);
+        let code = apply_visitor(source, Syntax::Es(Default::default()), &mut inserter);
+        (code, inserter.misused_macros)
+    }
+
+    fn apply_transform_tsx(source: &str) -> String {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let mut inserter = ImportInserter::new(&registry, TransformOptions::default());
+        let syntax = Syntax::Typescript(TsSyntax {
+            tsx: true,
+            ..Default::default()
+        });
+        apply_visitor(source, syntax, &mut inserter)
+    }
+
+    fn apply_remover(source: &str) -> (String, Vec<(String, String)>) {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            scope: ScriptScope {
+                setup: true,
+                page: false,
+            },
+            ..Default::default()
+        };
+        let mut remover = ImportRemover::new(&registry, options);
+        let code = apply_visitor(source, Syntax::Typescript(Default::default()), &mut remover);
+        (code, names(&remover.removed_imports))
+    }
+
+    fn names(changes: &[ImportChange]) -> Vec<(String, String)> {
+        changes
+            .iter()
+            .map(|change| (change.name.clone(), change.module.clone()))
+            .collect()
+    }
+
+    fn apply_visitor<V: VisitMut>(source: &str, syntax: Syntax, visitor: &mut V) -> String {
         let cm: Lrc<SourceMap> = Default::default();
         let fm = cm.new_source_file(swc_common::FileName::Anon.into(), source.into());
 
-        let lexer = Lexer::new(
-            Syntax::Es(Default::default()),
-            EsVersion::latest(),
-            StringInput::from(&*fm),
-            None,
-        );
+        let lexer = Lexer::new(syntax, EsVersion::latest(), StringInput::from(&*fm), None);
 
         let mut parser = Parser::new_from(lexer);
         let mut module = parser.parse_module().expect("Failed to parse module");
 
-        let mut inserter = ImportInserter::new();
-        module.visit_mut_with(&mut inserter);
+        module.visit_mut_with(visitor);
 
         let mut buf = vec![];
         {
@@ -230,6 +690,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_insert_import_for_jsx_components() {
+        let source = r#"
+        import * as Icons from './icons';
+        export default defineNuxtComponent({
+            render: () => (
+                <ClientOnly>
+                    <NuxtLink to="/about">About</NuxtLink>
+                    <Icons.Home />
+                    <svg:rect />
+                    <div />
+                    <useState />
+                </ClientOnly>
+            ),
+        });
+        "#;
+        let transformed = apply_transform_tsx(source);
+
+        assert!(transformed.contains("import { defineNuxtComponent } from \"#imports\";"));
+        assert!(
+            transformed.contains("import { ClientOnly, NuxtLink } from \"#components\";"),
+            "JSX components should be imported: {}",
+            transformed
+        );
+        assert!(
+            !transformed.contains("useState }"),
+            "Functions used as JSX tags are not components"
+        );
+    }
+
+    #[test]
+    fn test_skip_names_declared_in_the_script() {
+        let source = r#"
+        function watch(source) {
+            return source;
+        }
+        class h {}
+        const { ref } = createStore();
+        watch(1);
+        new h();
+        ref();
+        items.map(function provide(inject) {
+            return inject() + provide();
+        });
+        try {
+            useRoute();
+        } catch (useState) {
+            useState();
+        }
+        "#;
+        let transformed = apply_transform(source);
+
+        assert!(
+            transformed.starts_with("import { useRoute } from \"#imports\";"),
+            "Only names that are not declared should be imported: {}",
+            transformed
+        );
+        assert_eq!(
+            transformed.matches("import {").count(),
+            1,
+            "{}",
+            transformed
+        );
+    }
+
     #[test]
     fn test_insert_import_when_mixed_with_existing_imports() {
         let source = r#"
@@ -258,4 +783,287 @@ mod tests {
             "No import should be added"
         );
     }
+
+    #[test]
+    fn test_macros_are_never_imported() {
+        let source = r#"
+        const props = withDefaults(defineProps(), { count: 0 });
+        const emit = defineEmits(['change']);
+        definePageMeta({ layout: 'default' });
+        const route = useRoute();
+        "#;
+        let page = ScriptScope {
+            setup: true,
+            page: true,
+        };
+        let (transformed, misused) = apply_transform_in(source, page);
+
+        assert!(transformed.contains("import { useRoute } from \"#imports\";"));
+        for name in [
+            "withDefaults",
+            "defineProps",
+            "defineEmits",
+            "definePageMeta",
+        ] {
+            assert!(
+                !transformed.contains(&format!("import {{ {} }}", name)),
+                "Compiler macro {} should not be imported",
+                name
+            );
+        }
+        assert!(
+            misused.is_empty(),
+            "Macros are valid in a page script setup"
+        );
+    }
+
+    #[test]
+    fn test_misused_macros_are_reported() {
+        let source = r#"
+        definePageMeta({ layout: 'default' });
+        const props = defineProps();
+        "#;
+        let component = ScriptScope {
+            setup: true,
+            page: false,
+        };
+        let (_, misused) = apply_transform_in(source, component);
+        assert_eq!(misused, vec!["definePageMeta"]);
+
+        let (transformed, misused) = apply_transform_in(source, ScriptScope::default());
+        assert_eq!(misused, vec!["definePageMeta", "defineProps"]);
+        assert!(
+            !transformed.contains("import "),
+            "No import should be added"
+        );
+    }
+
+    #[test]
+    fn test_insert_source_module_imports() {
+        let source = r#"
+        const count = ref(0);
+        const route = useRoute();
+        onBeforeRouteLeave(() => {});
+        const doubled = computed(() => count.value * 2);
+        "#;
+        let options = TransformOptions {
+            style: ImportStyle::Source,
+            ..Default::default()
+        };
+        let (transformed, _) = apply_transform_with(source, options);
+
+        assert!(transformed.contains("import { ref, computed } from \"vue\";"));
+        assert!(transformed.contains("import { useRoute } from \"nuxt/app\";"));
+        assert!(transformed.contains("import { onBeforeRouteLeave } from \"vue-router\";"));
+        assert!(!transformed.contains("#imports"));
+    }
+
+    #[test]
+    fn test_source_style_reports_virtual_only_components() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            style: ImportStyle::Source,
+            ..Default::default()
+        };
+        let mut inserter = ImportInserter::new(&registry, options);
+        let syntax = Syntax::Es(EsSyntax {
+            jsx: true,
+            ..Default::default()
+        });
+        let source = "const view = () => <Suspense><NuxtLink to=\"/\" /></Suspense>;";
+        let transformed = apply_visitor(source, syntax, &mut inserter);
+
+        assert!(transformed.contains("import { Suspense } from \"vue\";"));
+        assert!(transformed.contains("import { NuxtLink } from \"#components\";"));
+        assert_eq!(inserter.virtual_imports, ["NuxtLink"]);
+    }
+
+    #[test]
+    fn test_declare_module_blocks_do_not_reset_imports() {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let options = TransformOptions {
+            style: ImportStyle::Source,
+            ..Default::default()
+        };
+        let mut inserter = ImportInserter::new(&registry, options);
+        let syntax = Syntax::Typescript(Default::default());
+        let source = r#"
+        import { useState } from '#imports';
+        export default defineNuxtPlugin(() => {
+            const route = useRoute();
+            return { provide: { hello: () => useState('hello') } };
+        });
+        declare module '#app' {
+            interface NuxtApp {
+                $hello: () => string;
+            }
+        }
+        "#;
+        let transformed = apply_visitor(source, syntax, &mut inserter);
+
+        assert!(inserter.rewrote_imports);
+        assert!(!transformed.contains("#imports"), "{}", transformed);
+        let names: Vec<&str> = inserter
+            .added_imports
+            .iter()
+            .map(|change| change.name.as_str())
+            .collect();
+        assert_eq!(names, ["defineNuxtPlugin", "useRoute"]);
+    }
+
+    #[test]
+    fn test_skip_own_exports_of_scanned_files() {
+        // `composables/useCart.ts` の中で同じファイルの export を使っても自分自身を import しない
+        let registry = Registry::default().with_entry(RegistryEntry {
+            name: "useCart".into(),
+            module: "#imports".into(),
+            source: "~/composables/useCart".into(),
+            kind: ImportKind::Function,
+        });
+        let source = r#"
+        export const useCart = () => ({ total: 0 });
+        export function useCartTotal() {
+            return useCart().total;
+        }
+        "#;
+
+        for style in [ImportStyle::Virtual, ImportStyle::Source] {
+            let options = TransformOptions {
+                style,
+                ..Default::default()
+            };
+            let mut inserter = ImportInserter::new(&registry, options);
+            let transformed = apply_visitor(source, Syntax::Es(Default::default()), &mut inserter);
+            assert!(inserter.added_imports.is_empty(), "{}", transformed);
+        }
+    }
+
+    #[test]
+    fn test_expand_existing_virtual_imports() {
+        let source = r#"
+        import { ref, useFetch as fetchData, myHelper } from '#imports';
+        import { NuxtLink } from '#components';
+        import '#imports';
+
+        const count = ref(0);
+        const data = fetchData('/api');
+        myHelper();
+        "#;
+        let options = TransformOptions {
+            style: ImportStyle::Source,
+            ..Default::default()
+        };
+        let (transformed, _) = apply_transform_with(source, options);
+
+        assert!(transformed.contains("import { ref } from \"vue\";"));
+        assert!(transformed.contains("import { useFetch as fetchData } from \"nuxt/app\";"));
+        assert!(transformed.contains("import { myHelper } from '#imports';"));
+        assert!(transformed.contains("import { NuxtLink } from '#components';"));
+        assert!(transformed.contains("import '#imports';"));
+        assert!(
+            !transformed.contains("import { ref } from \"#imports\""),
+            "Already imported names should not be imported again"
+        );
+    }
+
+    #[test]
+    fn test_remove_auto_imported_specifiers() {
+        let source = r#"
+        import { ref, computed, nextTick as tick } from 'vue';
+        import { useFetch, useRoute } from '#imports';
+        import { defineProps } from 'vue';
+        import { format } from 'date-fns';
+
+        const count = ref(0);
+        "#;
+        let (transformed, removed) = apply_remover(source);
+
+        assert!(transformed.contains("import { nextTick as tick } from 'vue';"));
+        assert!(transformed.contains("import { format } from 'date-fns';"));
+        assert!(
+            !transformed.contains("#imports"),
+            "Empty imports should be dropped"
+        );
+        assert!(!transformed.contains("defineProps }"));
+        assert_eq!(
+            removed,
+            vec![
+                ("ref".to_string(), "vue".to_string()),
+                ("computed".to_string(), "vue".to_string()),
+                ("useFetch".to_string(), "#imports".to_string()),
+                ("useRoute".to_string(), "#imports".to_string()),
+                ("defineProps".to_string(), "vue".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_keeps_type_only_and_side_effect_imports() {
+        let source = r#"
+        import type { Ref } from 'vue';
+        import { type ComputedRef, watch } from 'vue';
+        import 'vue';
+        import { ref } from 'some-other-lib';
+        "#;
+        let (transformed, removed) = apply_remover(source);
+
+        assert!(transformed.contains("import type { Ref } from 'vue';"));
+        assert!(transformed.contains("import { type ComputedRef } from 'vue';"));
+        assert!(transformed.contains("import 'vue';"));
+        assert!(transformed.contains("import { ref } from 'some-other-lib';"));
+        assert_eq!(removed, vec![("watch".to_string(), "vue".to_string())]);
+    }
+
+    fn apply_unused_remover(source: &str, template: &str) -> (String, Vec<(String, String)>) {
+        let registry = Registry::builtin(NuxtVersion::LATEST, Context::App);
+        let mut remover =
+            UnusedImportRemover::new(&registry, crate::template::template_references(template));
+        let code = apply_visitor(source, Syntax::Typescript(Default::default()), &mut remover);
+        (code, names(&remover.removed_imports))
+    }
+
+    #[test]
+    fn test_remove_unused_auto_imports() {
+        let source = r#"
+        import { useFetch, useRoute, ref as vueRef } from '#imports';
+        import { computed, watch } from 'vue';
+        import { NuxtLink, ClientOnly } from '#components';
+        import { debounce } from 'lodash-es';
+
+        const route = useRoute();
+        const total = computed(() => 1);
+        "#;
+        let (transformed, removed) = apply_unused_remover(source, "<NuxtLink to=\"/\" />");
+
+        assert!(transformed.contains("import { useRoute } from '#imports';"));
+        assert!(transformed.contains("import { computed } from 'vue';"));
+        assert!(transformed.contains("import { NuxtLink } from '#components';"));
+        assert!(
+            transformed.contains("import { debounce } from 'lodash-es';"),
+            "Imports from other modules should be kept even if unused"
+        );
+        assert_eq!(
+            removed,
+            vec![
+                ("useFetch".to_string(), "#imports".to_string()),
+                ("vueRef".to_string(), "#imports".to_string()),
+                ("watch".to_string(), "vue".to_string()),
+                ("ClientOnly".to_string(), "#components".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_unused_drops_empty_declaration() {
+        let source = r#"
+        import { useFetch } from '#imports';
+        import type { Ref } from 'vue';
+
+        const value = 1;
+        "#;
+        let (transformed, _) = apply_unused_remover(source, "");
+
+        assert!(!transformed.contains("useFetch"));
+        assert!(transformed.contains("import type { Ref } from 'vue';"));
+    }
 }
@@ -0,0 +1,308 @@
+use crate::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// 並列に処理したファイルを同時にステージすると `index.lock` が競合するので 1 つずつ行う
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// コミットされていない変更の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirtyState {
+    /// ステージされていない変更がある
+    Dirty,
+    /// 変更がステージされている
+    Staged,
+    /// git で追跡されていない
+    Untracked,
+}
+
+impl fmt::Display for DirtyState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dirty => write!(f, "dirty"),
+            Self::Staged => write!(f, "staged"),
+            Self::Untracked => write!(f, "untracked"),
+        }
+    }
+}
+
+/// `target` 以下でコミットされていない変更があるファイル (`target` からの相対パス)
+///
+/// git がないか、`target` が git リポジトリの中でなければ `None` を返す。
+pub fn dirty_files(target: &Path) -> Option<Vec<(PathBuf, DirtyState)>> {
+    let prefix = git(target, &["rev-parse", "--show-prefix"])?;
+    let prefix = prefix.trim_end_matches('\n');
+    let status = git(
+        target,
+        &[
+            "status",
+            "--porcelain",
+            "-z",
+            "--untracked-files=all",
+            "--",
+            ".",
+        ],
+    )?;
+    Some(parse_status(&status, prefix))
+}
+
+/// `target` 以下で `since` から変更されたファイル (作業ツリーとの差分、削除されたファイルは除く)
+///
+/// `git diff` には出てこない、まだ追跡されていない新しいファイル (無視されているものを除く) も含める。
+pub fn changed_files(target: &Path, since: &str) -> Result<Vec<PathBuf>, Error> {
+    let output = run(
+        target,
+        &[
+            "diff",
+            "--name-only",
+            "-z",
+            "--relative",
+            "--diff-filter=ACMR",
+            since,
+            "--",
+        ],
+        None,
+    )?;
+    let untracked = run(
+        target,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "-z",
+            "--",
+            ".",
+        ],
+        None,
+    )?;
+    let mut files = paths(&output);
+    files.extend(paths(&untracked));
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// `target` 以下でステージされているファイル (削除されたファイルは除く)
+pub fn staged_files(target: &Path) -> Result<Vec<PathBuf>, Error> {
+    let output = run(
+        target,
+        &[
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--relative",
+            "--diff-filter=ACMR",
+            "--",
+        ],
+        None,
+    )?;
+    Ok(paths(&output))
+}
+
+/// ステージされている内容 (`relative` は `target` からの相対パス)
+pub fn staged_content(target: &Path, relative: &Path) -> Result<String, Error> {
+    run(target, &["show", &index_path(relative)], None)
+}
+
+/// `content` をステージし直す (作業ツリーのファイルは変えない)
+pub fn stage(target: &Path, relative: &Path, content: &str) -> Result<(), Error> {
+    let _lock = INDEX_LOCK.lock().unwrap();
+    let relative = relative.to_string_lossy();
+    let entry = run(target, &["ls-files", "--stage", "--", &relative], None)?;
+    let mode = entry
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| Error::Git(format!("{} is not in the index", relative)))?;
+    let object = run(
+        target,
+        &["hash-object", "-w", "--stdin", "--path", &relative],
+        Some(content),
+    )?;
+    // `--cacheinfo` のパスはリポジトリのルートからの相対パス
+    let prefix = run(target, &["rev-parse", "--show-prefix"], None)?;
+    let cache_info = format!(
+        "{},{},{}{}",
+        mode,
+        object.trim_end(),
+        prefix.trim_end_matches('\n'),
+        relative
+    );
+    run(target, &["update-index", "--cacheinfo", &cache_info], None)?;
+    Ok(())
+}
+
+/// `git show` で使うインデックス内のパス (カレントディレクトリからの相対パス)
+fn index_path(relative: &Path) -> String {
+    format!(":./{}", relative.to_string_lossy())
+}
+
+fn paths(output: &str) -> Vec<PathBuf> {
+    output
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// `git status --porcelain -z` の出力を読む (パスはリポジトリのルートから `prefix` を除いたもの)
+fn parse_status(status: &str, prefix: &str) -> Vec<(PathBuf, DirtyState)> {
+    let mut files = vec![];
+    let mut fields = status.split('\0').filter(|field| !field.is_empty());
+    while let Some(field) = fields.next() {
+        let (Some(code), Some(path)) = (field.get(..2), field.get(3..)) else {
+            continue;
+        };
+        // 名前の変更とコピーは元のパスが続く
+        if code.contains(['R', 'C']) {
+            fields.next();
+        }
+        let state = match code.as_bytes() {
+            b"??" => DirtyState::Untracked,
+            [_, b' '] => DirtyState::Staged,
+            _ => DirtyState::Dirty,
+        };
+        let path = path.strip_prefix(prefix).unwrap_or(path);
+        files.push((PathBuf::from(path), state));
+    }
+    files
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    run(dir, args, None).ok()
+}
+
+/// `dir` で git を実行して標準出力を返す (`input` があれば標準入力に渡す)
+fn run(dir: &Path, args: &[&str], input: Option<&str>) -> Result<String, Error> {
+    let error = |message: String| Error::Git(format!("git {}: {}", args.join(" "), message));
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| error(e.to_string()))?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|e| error(e.to_string()))?;
+    }
+    let output = child.wait_with_output().map_err(|e| error(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(error(stderr.trim_end().to_string()));
+    }
+    String::from_utf8(output.stdout).map_err(|e| error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_status() {
+        let status = " M src/pages/index.vue\0M  src/a.ts\0MM src/b.ts\0?? src/new.ts\0R  src/c.ts\0src/old.ts\0";
+
+        assert_eq!(
+            parse_status(status, "src/"),
+            vec![
+                (PathBuf::from("pages/index.vue"), DirtyState::Dirty),
+                (PathBuf::from("a.ts"), DirtyState::Staged),
+                (PathBuf::from("b.ts"), DirtyState::Dirty),
+                (PathBuf::from("new.ts"), DirtyState::Untracked),
+                (PathBuf::from("c.ts"), DirtyState::Staged),
+            ]
+        );
+    }
+
+    /// `clean.ts` と `edited.ts` を `src/` に、`outside.ts` をルートにコミットしたリポジトリ
+    fn repository() -> Option<(tempfile::TempDir, PathBuf)> {
+        let dir = tempdir().expect("Failed to create temp dir");
+        git(dir.path(), &["init", "-q"])?;
+
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("clean.ts"), "useState();\n").unwrap();
+        fs::write(src.join("edited.ts"), "useState();\n").unwrap();
+        fs::write(dir.path().join("outside.ts"), "useState();\n").unwrap();
+        git(dir.path(), &["add", "."]).unwrap();
+        git(
+            dir.path(),
+            &[
+                "-c",
+                "user.name=denux",
+                "-c",
+                "user.email=denux@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "init",
+            ],
+        )
+        .unwrap();
+        Some((dir, src))
+    }
+
+    #[test]
+    fn test_dirty_files_in_repository() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        assert_eq!(dirty_files(dir.path()), None, "Not a git repository");
+        let Some((dir, src)) = repository() else {
+            return;
+        };
+
+        fs::write(src.join("edited.ts"), "useFetch();\n").unwrap();
+        fs::write(src.join("new.ts"), "useRoute();\n").unwrap();
+        fs::write(dir.path().join("outside.ts"), "useFetch();\n").unwrap();
+
+        let mut files = dirty_files(&src).expect("Failed to read git status");
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(
+            files,
+            vec![
+                (PathBuf::from("edited.ts"), DirtyState::Dirty),
+                (PathBuf::from("new.ts"), DirtyState::Untracked),
+            ]
+        );
+        assert_eq!(
+            changed_files(&src, "HEAD").unwrap(),
+            vec![PathBuf::from("edited.ts"), PathBuf::from("new.ts")],
+            "Untracked files are new since any ref"
+        );
+        assert!(changed_files(&src, "no-such-ref").is_err());
+    }
+
+    #[test]
+    fn test_stage_updates_only_the_index() {
+        let Some((dir, src)) = repository() else {
+            return;
+        };
+
+        fs::write(src.join("edited.ts"), "useFetch();\n").unwrap();
+        git(dir.path(), &["add", "src/edited.ts"]).unwrap();
+        fs::write(src.join("edited.ts"), "useFetch();\n// not staged\n").unwrap();
+
+        assert_eq!(
+            staged_files(&src).unwrap(),
+            vec![PathBuf::from("edited.ts")]
+        );
+        let relative = Path::new("edited.ts");
+        assert_eq!(staged_content(&src, relative).unwrap(), "useFetch();\n");
+
+        let updated = "import { useFetch } from '#imports';\nuseFetch();\n";
+        stage(&src, relative, updated).expect("Failed to stage");
+        assert_eq!(staged_content(&src, relative).unwrap(), updated);
+        assert_eq!(
+            fs::read_to_string(src.join("edited.ts")).unwrap(),
+            "useFetch();\n// not staged\n"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::cli::Cli;
+use crate::{is_dry_run, load_filter, load_registries, process_entry, EXIT_FAILURE, INTERRUPTED};
+use denux::error::Error;
+use denux::file_processor::{FileOutcome, FileResult};
+use denux::filter::{relative_path, walk, PathFilter};
+use denux::journal::Journal;
+use denux::registry::RegistrySet;
+use denux::report;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 続けて起きた変更をまとめて処理するまでの待ち時間
+const DEBOUNCE: Duration = Duration::from_millis(200);
+/// Ctrl-C を確かめる間隔
+const POLL: Duration = Duration::from_millis(200);
+/// Nuxt が型定義を生成するディレクトリ
+const NUXT_DIR: &str = ".nuxt";
+
+/// `watch`: 対象ディレクトリを監視し、変更されたファイルをその都度処理する
+///
+/// レジストリはメモリに持ち続け、`composables/` や `nuxt.config.ts` などが
+/// 変更されたときだけ作り直す。ファイルごとの変更は 1 回の実行として `.denux/` に記録する。
+pub fn run(args: &Cli) -> ExitCode {
+    match watch(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn watch(args: &Cli) -> Result<(), Error> {
+    let filter = load_filter(args)?;
+    let (nuxt_version, mut registries) = load_registries(args)?;
+    // 通知されるパスは絶対パスなので、対象ディレクトリも絶対パスにして比べる
+    let root = args.target.canonicalize().map_err(Error::Read)?;
+
+    let (tx, rx) = mpsc::channel();
+    // ファイルを開いただけの通知 (レジストリを読み直したときなど) には反応しない
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                let _ = tx.send(event.paths);
+            }
+        }
+    })
+    .map_err(|e| Error::Watch(e.to_string()))?;
+    watch_tree(&mut watcher, &root, &root, &filter)?;
+    watch_nuxt_dir(&mut watcher, &root)?;
+    println!(
+        "Watching {:?} with the Nuxt {} auto-import registry (press Ctrl-C to stop)",
+        args.target, nuxt_version
+    );
+
+    // 自分で書き込んだ内容 (その通知では処理し直さない)
+    let mut written: HashMap<PathBuf, String> = HashMap::new();
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        let mut paths = match rx.recv_timeout(POLL) {
+            Ok(paths) => paths,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        // エディタの保存などで続けて届く通知をまとめる
+        while let Ok(more) = rx.recv_timeout(DEBOUNCE) {
+            paths.extend(more);
+        }
+
+        // 新しくできたディレクトリも監視し、監視を始める前に作られた中のファイルも処理する
+        let mut created = vec![];
+        for dir in paths.iter().filter(|path| path.is_dir()) {
+            let watched = if *dir == root.join(NUXT_DIR) {
+                watch_nuxt_dir(&mut watcher, &root).map(|_| vec![])
+            } else {
+                watch_tree(&mut watcher, &root, dir, &filter)
+            };
+            match watched {
+                Ok(files) => created.extend(files),
+                Err(e) => eprintln!("Error: {}", e),
+            }
+        }
+        paths.extend(created);
+
+        let mut changed: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|path| path.strip_prefix(&root).ok().map(Path::to_path_buf))
+            .collect();
+        changed.sort();
+        changed.dedup();
+
+        if changed
+            .iter()
+            .any(|relative| RegistrySet::is_input(relative))
+        {
+            match load_registries(args) {
+                Ok((_, reloaded)) => {
+                    registries = reloaded;
+                    println!("Reloaded the auto-import registry");
+                }
+                Err(e) => eprintln!("Error: {}", e),
+            }
+        }
+
+        let journal = Journal::new(&args.target);
+        let statuses: Vec<(PathBuf, FileResult)> = changed
+            .iter()
+            .map(|relative| args.target.join(relative))
+            .filter(|path| should_process(path, args, &filter, &written))
+            .map(|path| {
+                let status = process_entry(&path, args, &registries, &journal);
+                (path, status)
+            })
+            .collect();
+
+        for (path, status) in &statuses {
+            if let Ok(FileOutcome::Changed { updated, .. }) = status {
+                if !is_dry_run(args) {
+                    written.insert(path.clone(), updated.clone());
+                }
+            }
+        }
+        report::print_text(&statuses, args.check, args.dry_run, true);
+    }
+    Ok(())
+}
+
+/// `dir` とその下の除外されていないディレクトリを監視し、中にあるファイルを返す
+///
+/// `node_modules/` や `.output/` などの変更を通知させないように、再帰的に監視せず
+/// 走査で入ったディレクトリごとに監視を登録する。
+fn watch_tree(
+    watcher: &mut impl Watcher,
+    root: &Path,
+    dir: &Path,
+    filter: &Arc<PathFilter>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    // `dir` が `root` の下のディレクトリのときも、除外の glob は `root` からの相対パスで判定する
+    for entry in walk(dir, filter.clone()).flatten() {
+        let path = entry.path();
+        let excluded = relative_path(path, root)
+            .ancestors()
+            .any(|dir| !dir.as_os_str().is_empty() && filter.is_excluded(dir));
+        if excluded {
+            continue;
+        }
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir())
+        {
+            watcher
+                .watch(path, RecursiveMode::NonRecursive)
+                .map_err(|e| Error::Watch(e.to_string()))?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// `.nuxt/` の型定義の変更でレジストリを作り直せるよう、除外されていても `.nuxt/` を監視する
+///
+/// `.nuxt/` は `nuxi prepare` などで後から作られることが多いので、なければ何もせず、
+/// 作られたとき (ルートの監視で通知される) に改めて呼ぶ。
+fn watch_nuxt_dir(watcher: &mut impl Watcher, root: &Path) -> Result<(), Error> {
+    let dir = root.join(NUXT_DIR);
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| Error::Watch(e.to_string()))
+}
+
+/// 変更が通知されたファイルを処理するか
+///
+/// 削除されたファイル、対象外のファイル、最後に自分で書き込んだ内容のままのファイルは処理しない。
+fn should_process(
+    path: &Path,
+    args: &Cli,
+    filter: &PathFilter,
+    written: &HashMap<PathBuf, String>,
+) -> bool {
+    let relative = relative_path(path, &args.target);
+    if !path.is_file()
+        || !filter.has_target_extension(path)
+        || !filter.is_included_with_parents(relative)
+    {
+        return false;
+    }
+    match written.get(path) {
+        Some(content) => fs::read_to_string(path).is_ok_and(|current| &current != content),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// 監視を登録したパスを記録するだけの Watcher
+    #[derive(Default)]
+    struct RecordingWatcher(Vec<(PathBuf, RecursiveMode)>);
+
+    impl Watcher for RecordingWatcher {
+        fn new<F: notify::EventHandler>(_: F, _: notify::Config) -> notify::Result<Self> {
+            Ok(Self::default())
+        }
+
+        fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+            self.0.push((path.to_path_buf(), mode));
+            Ok(())
+        }
+
+        fn unwatch(&mut self, _: &Path) -> notify::Result<()> {
+            Ok(())
+        }
+
+        fn kind() -> notify::WatcherKind {
+            notify::WatcherKind::NullWatcher
+        }
+    }
+
+    #[test]
+    fn test_watch_tree_skips_excluded_directories() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let root = dir.path();
+        for sub in [
+            "pages/admin",
+            "node_modules/vue",
+            ".output/server",
+            "legacy/old",
+        ] {
+            fs::create_dir_all(root.join(sub)).expect("Failed to create dir");
+        }
+        fs::write(root.join("pages/admin/index.vue"), "").expect("Failed to write file");
+        let filter = Arc::new(PathFilter::new(&[], &["legacy".into()]).unwrap());
+
+        let mut watcher = RecordingWatcher::default();
+        watch_tree(&mut watcher, root, root, &filter).expect("Failed to watch");
+        let mut watched: Vec<PathBuf> = watcher
+            .0
+            .iter()
+            .map(|(path, mode)| {
+                assert_eq!(*mode, RecursiveMode::NonRecursive);
+                relative_path(path, root).to_path_buf()
+            })
+            .collect();
+        watched.sort();
+        assert_eq!(
+            watched,
+            [PathBuf::new(), "pages".into(), "pages/admin".into()]
+        );
+
+        // 後からできたディレクトリも `root` からの相対パスで除外を判定する
+        fs::create_dir_all(root.join("legacy/old/new")).expect("Failed to create dir");
+        let mut watcher = RecordingWatcher::default();
+        let files =
+            watch_tree(&mut watcher, root, &root.join("pages"), &filter).expect("Failed to watch");
+        assert_eq!(files, [root.join("pages/admin/index.vue")]);
+        let files = watch_tree(&mut watcher, root, &root.join("legacy/old"), &filter)
+            .expect("Failed to watch");
+        assert!(files.is_empty());
+        assert_eq!(watcher.0.len(), 2, "{:?}", watcher.0);
+    }
+
+    #[test]
+    fn test_watch_nuxt_dir() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let root = dir.path();
+        let mut watcher = RecordingWatcher::default();
+
+        watch_nuxt_dir(&mut watcher, root).expect("Failed to watch");
+        assert!(watcher.0.is_empty(), ".nuxt does not exist yet");
+
+        fs::create_dir(root.join(".nuxt")).expect("Failed to create dir");
+        watch_tree(
+            &mut watcher,
+            root,
+            root,
+            &Arc::new(PathFilter::new(&[], &[]).unwrap()),
+        )
+        .expect("Failed to watch");
+        watch_nuxt_dir(&mut watcher, root).expect("Failed to watch");
+        assert_eq!(
+            watcher.0,
+            [
+                (root.to_path_buf(), RecursiveMode::NonRecursive),
+                (root.join(".nuxt"), RecursiveMode::NonRecursive),
+            ],
+            ".nuxt is excluded from processing but still watched"
+        );
+    }
+}